@@ -1,7 +1,7 @@
 #![no_std]
 
 use sails_rs::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     gstd::{exec, msg},
     prelude::*,
 };
@@ -45,6 +45,8 @@ struct SplitsState {
     owner_groups: BTreeMap<ActorId, Vec<GroupId>>,
     // Track distributed amounts per group for accounting
     distributed: BTreeMap<GroupId, u128>,
+    // Programs (e.g. StreamCore) allowed to distribute to any group
+    distributors: BTreeSet<ActorId>,
 }
 
 impl SplitsState {
@@ -55,10 +57,35 @@ impl SplitsState {
             groups: BTreeMap::new(),
             owner_groups: BTreeMap::new(),
             distributed: BTreeMap::new(),
+            distributors: BTreeSet::new(),
         }
     }
 }
 
+/// Splits `amount` by recipient weight. Rounding dust goes to the last
+/// recipient so the shares always add up to `amount`.
+fn split_amount(group: &SplitGroup, amount: u128) -> Vec<DistributionPreview> {
+    let mut remaining = amount;
+    let last = group.recipients.len() - 1;
+    group
+        .recipients
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let share = if i == last {
+                remaining
+            } else {
+                (amount * r.weight as u128) / group.total_weight as u128
+            };
+            remaining -= share;
+            DistributionPreview {
+                address: r.address,
+                amount: share,
+            }
+        })
+        .collect()
+}
+
 static mut STATE: Option<SplitsState> = None;
 
 fn state() -> &'static SplitsState {
@@ -164,21 +191,42 @@ impl SplitsService {
         }
     }
 
-    pub fn distribute(&mut self, group_id: GroupId, token: ActorId, amount: u128) {
+    /// Records a distribution to the group and returns each recipient's
+    /// share. Callable by the group owner or an approved distributor, which
+    /// moves the tokens itself: it splits with `preview_distribution` and
+    /// records here once the transfer has succeeded.
+    pub fn distribute(
+        &mut self,
+        group_id: GroupId,
+        token: ActorId,
+        amount: u128,
+    ) -> Vec<DistributionPreview> {
         assert!(amount > 0, "Amount must be greater than zero");
 
         let caller = msg::source();
         let s = state_mut();
         let group = s.groups.get(&group_id).expect("Group not found");
-        assert!(group.owner == caller, "Only the owner can distribute");
+        assert!(
+            group.owner == caller || s.distributors.contains(&caller),
+            "Only the owner or a distributor can distribute"
+        );
 
-        // Record the distribution (actual token transfers would require cross-contract calls)
+        let shares = split_amount(group, amount);
         let total = s.distributed.entry(group_id).or_insert(0);
         *total += amount;
 
-        // In production, this would iterate recipients and call TokenVault
-        // to transfer proportional amounts. For now we record the intent.
-        let _ = token; // token used in cross-contract call
+        let _ = token; // token is moved by the caller
+        shares
+    }
+
+    pub fn set_distributor(&mut self, program: ActorId, allowed: bool) {
+        let s = state_mut();
+        assert!(msg::source() == s.admin, "Only admin can set distributors");
+        if allowed {
+            s.distributors.insert(program);
+        } else {
+            s.distributors.remove(&program);
+        }
     }
 
     // --- Queries ---
@@ -193,17 +241,7 @@ impl SplitsService {
 
     pub fn preview_distribution(&self, group_id: GroupId, amount: u128) -> Vec<DistributionPreview> {
         let group = state().groups.get(&group_id).expect("Group not found");
-        group
-            .recipients
-            .iter()
-            .map(|r| {
-                let share = (amount * r.weight as u128) / group.total_weight as u128;
-                DistributionPreview {
-                    address: r.address,
-                    amount: share,
-                }
-            })
-            .collect()
+        split_amount(group, amount)
     }
 
     pub fn total_groups(&self) -> u64 {
@@ -218,4 +256,8 @@ impl SplitsService {
     pub fn get_total_distributed(&self, group_id: GroupId) -> u128 {
         state().distributed.get(&group_id).copied().unwrap_or(0)
     }
+
+    pub fn is_distributor(&self, program: ActorId) -> bool {
+        state().distributors.contains(&program)
+    }
 }
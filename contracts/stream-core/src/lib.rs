@@ -5,7 +5,7 @@ use sails_rs::{
     gstd::{exec, msg},
     prelude::*,
 };
-use gstd::msg as gstd_msg;
+use gstd::{msg as gstd_msg, ReservationId, ReservationIdExt};
 
 // ---------------------------------------------------------------------------
 // Helpers
@@ -19,6 +19,161 @@ fn encode_call(service: &str, method: &str, args: impl Encode) -> Vec<u8> {
     payload
 }
 
+/// Upper bound for the share of a liquidated stream's buffer paid to the liquidator.
+pub const MAX_LIQUIDATION_REWARD_BPS: u16 = 5_000;
+const BPS_DENOMINATOR: u128 = 10_000;
+/// Keeps curve evaluation bounded in gas.
+pub const MAX_CURVE_SEGMENTS: usize = 32;
+
+/// Upper bound for `limit` in paginated stream queries.
+pub const MAX_PAGE_SIZE: u32 = 100;
+/// Upper bound for the protocol fee taken from stream withdrawals.
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+/// Caps the queued rate changes per stream so settling stays bounded in gas.
+pub const MAX_RATE_CHANGES: usize = 16;
+/// Caps the streams touched by one batch command so it fits in a block.
+pub const MAX_BATCH_SIZE: usize = 256;
+
+/// Gas for the TokenVault's own handling of a call.
+const VAULT_GAS_LIMIT: u64 = 5_000_000_000;
+/// Gas the TokenVault attaches to every VFT transfer it forwards. Gear takes
+/// it out of the vault call's budget, so `call_vault_with_transfers` adds it
+/// on top of `VAULT_GAS_LIMIT` for each one.
+pub const VFT_TRANSFER_GAS: u64 = 5_000_000_000;
+
+/// Number of VFT transfers the vault forwards to pay out `token` once;
+/// native VARA needs no gas of its own.
+fn vft_transfers(token: ActorId) -> u64 {
+    u64::from(token != ActorId::zero())
+}
+
+/// Sends a `VaultService` command to the TokenVault and waits for its reply.
+/// The vault panics on every rejected call, which comes back as an error reply.
+async fn call_vault(vault: ActorId, method: &str, args: impl Encode) -> Result<(), StreamError> {
+    call_vault_with_transfers(vault, method, args, 0).await
+}
+
+/// `call_vault` for a command during which the vault forwards `transfers`
+/// VFT transfers.
+async fn call_vault_with_transfers(
+    vault: ActorId,
+    method: &str,
+    args: impl Encode,
+    transfers: u64,
+) -> Result<(), StreamError> {
+    let gas = VAULT_GAS_LIMIT.saturating_add(VFT_TRANSFER_GAS.saturating_mul(transfers));
+    let payload = encode_call("VaultService", method, args);
+    gstd_msg::send_bytes_with_gas_for_reply(vault, payload, gas, 0, 0)
+        .map_err(|_| StreamError::VaultCallFailed)?
+        .await
+        .map_err(|_| StreamError::VaultRejected)?;
+    Ok(())
+}
+
+/// Asks the TokenVault how much is still allocated to each of `stream_ids`,
+/// in one message.
+async fn vault_stream_allocations(
+    vault: ActorId,
+    stream_ids: &[StreamId],
+) -> Result<Vec<u128>, StreamError> {
+    let payload = encode_call("VaultService", "GetStreamAllocations", stream_ids);
+    let reply = gstd_msg::send_bytes_with_gas_for_reply(vault, payload, VAULT_GAS_LIMIT, 0, 0)
+        .map_err(|_| StreamError::VaultCallFailed)?
+        .await
+        .map_err(|_| StreamError::VaultRejected)?;
+
+    let (_service, _method, allocations): (String, String, Vec<u128>) =
+        Decode::decode(&mut reply.as_slice()).map_err(|_| StreamError::VaultCallFailed)?;
+    Ok(allocations)
+}
+
+/// Least gas an auto-payout tick must carry to pay out and reschedule itself.
+pub const AUTO_PAYOUT_MIN_GAS: u64 = 20_000_000_000;
+/// Gas a tick keeps for itself after reserving the next tick's gas.
+const AUTO_PAYOUT_GAS_MARGIN: u64 = 2_000_000_000;
+
+/// Reserves `gas` and sends the next auto-payout tick for `stream_id` from
+/// that reservation, `delay` blocks from now.
+fn schedule_payout_tick(stream_id: StreamId, gas: u64, delay: u32) -> Result<MessageId, StreamError> {
+    let reservation = ReservationId::reserve(gas, delay.saturating_add(1))
+        .map_err(|_| StreamError::GasReservationFailed)?;
+    let payload = encode_call("StreamService", "ProcessAutoPayout", stream_id);
+    gstd_msg::send_bytes_delayed_from_reservation(reservation, exec::program_id(), payload, 0, delay)
+        .map_err(|_| StreamError::GasReservationFailed)
+}
+
+/// Gas a keeper check keeps for itself when it sends the next check.
+const KEEPER_GAS_MARGIN: u64 = 1_000_000_000;
+
+/// Expected block time, used to turn a buffer's remaining seconds into the
+/// block delay of a keeper check.
+const BLOCK_TIME_SECONDS: u64 = 3;
+
+const PERMISSION_GAS_LIMIT: u64 = 2_000_000_000;
+
+/// Asks the PermissionManager whether `grantee` may act for `granter` under
+/// `scope`. The reply carries the sails route prefix followed by the `bool`.
+async fn query_permission(
+    permission_manager: ActorId,
+    granter: ActorId,
+    grantee: ActorId,
+    scope: PermissionScope,
+) -> Result<bool, StreamError> {
+    let payload = encode_call("PermissionService", "HasPermission", (granter, grantee, scope));
+    let reply = gstd_msg::send_bytes_with_gas_for_reply(
+        permission_manager,
+        payload,
+        PERMISSION_GAS_LIMIT,
+        0,
+        0,
+    )
+    .map_err(|_| StreamError::PermissionCheckFailed)?
+    .await
+    .map_err(|_| StreamError::PermissionCheckFailed)?;
+
+    let (_service, _method, allowed): (String, String, bool) =
+        Decode::decode(&mut reply.as_slice()).map_err(|_| StreamError::PermissionCheckFailed)?;
+    Ok(allowed)
+}
+
+const SPLITS_GAS_LIMIT: u64 = 5_000_000_000;
+
+/// Sends a `SplitsService` message to the SplitsRouter and decodes the reply
+/// value that follows the sails route prefix.
+async fn call_splits<T: Decode>(
+    splits_router: ActorId,
+    method: &str,
+    args: impl Encode,
+) -> Result<T, StreamError> {
+    let payload = encode_call("SplitsService", method, args);
+    let reply = gstd_msg::send_bytes_with_gas_for_reply(splits_router, payload, SPLITS_GAS_LIMIT, 0, 0)
+        .map_err(|_| StreamError::SplitsCallFailed)?
+        .await
+        .map_err(|_| StreamError::SplitsCallFailed)?;
+
+    let (_service, _method, value): (String, String, T) =
+        Decode::decode(&mut reply.as_slice()).map_err(|_| StreamError::SplitsCallFailed)?;
+    Ok(value)
+}
+
+/// Upper bound for the gas a receiver hook may ask to be sent with.
+pub const MAX_RECEIVER_HOOK_GAS: u64 = 10_000_000_000;
+const MAX_HOOK_SERVICE_LEN: usize = 64;
+
+/// Rejects empty, oversized or repeating lists of stream ids.
+fn check_batch(stream_ids: &[StreamId]) -> Result<(), StreamError> {
+    let mut unique = stream_ids.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+    if stream_ids.is_empty()
+        || stream_ids.len() > MAX_BATCH_SIZE
+        || unique.len() != stream_ids.len()
+    {
+        return Err(StreamError::InvalidBatch);
+    }
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Types
 // ---------------------------------------------------------------------------
@@ -30,6 +185,198 @@ pub enum StreamStatus {
     Active,
     Paused,
     Stopped,
+    Completed,
+}
+
+/// Mirror of `permission_manager::PermissionScope`; variant order must match
+/// so the SCALE encoding lines up with the PermissionManager program.
+#[derive(Debug, Clone, Copy, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub enum PermissionScope {
+    CreateStream,
+    UpdateStream,
+    StopStream,
+    DepositOnBehalf,
+    FullAccess,
+}
+
+/// One stretch of a piecewise-linear curve, running `duration` seconds at
+/// `rate_bps` of the stream's base `flow_rate`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct CurveSegment {
+    pub duration: u64,
+    pub rate_bps: u32,
+}
+
+/// Shape of a stream's accrual over time, anchored at `start_time`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub enum ReleaseCurve {
+    /// `flow_rate` tokens every second.
+    Linear,
+    /// `flow_rate * period` tokens unlock at the end of every `period`.
+    Stepped { period: u64 },
+    /// Consecutive segments scale the base rate; once the last segment ends
+    /// the stream continues at the plain `flow_rate`.
+    PiecewiseLinear { segments: Vec<CurveSegment> },
+}
+
+impl ReleaseCurve {
+    fn is_valid(&self) -> bool {
+        match self {
+            Self::Linear => true,
+            Self::Stepped { period } => *period > 0,
+            Self::PiecewiseLinear { segments } => {
+                !segments.is_empty()
+                    && segments.len() <= MAX_CURVE_SEGMENTS
+                    && segments.iter().all(|seg| seg.duration > 0)
+            }
+        }
+    }
+
+    /// Amount released between `from` and `to` for a stream that was active
+    /// over the whole interval.
+    fn accrued(&self, flow_rate: u128, start_time: u64, from: u64, to: u64) -> u128 {
+        if to <= from {
+            return 0;
+        }
+        match self {
+            Self::Linear => flow_rate.saturating_mul((to - from) as u128),
+            Self::Stepped { period } => {
+                let step_floor = |t: u64| {
+                    start_time + t.saturating_sub(start_time) / period * period
+                };
+                flow_rate.saturating_mul((step_floor(to) - step_floor(from)) as u128)
+            }
+            Self::PiecewiseLinear { segments } => {
+                let overlap = |seg_start: u64, seg_end: u64| {
+                    to.min(seg_end).saturating_sub(from.max(seg_start)) as u128
+                };
+                let mut total: u128 = 0;
+                let mut seg_start = start_time;
+                for seg in segments {
+                    let seg_end = seg_start.saturating_add(seg.duration);
+                    let base = flow_rate.saturating_mul(overlap(seg_start, seg_end));
+                    total = total
+                        .saturating_add(base.saturating_mul(seg.rate_bps as u128) / BPS_DENOMINATOR);
+                    seg_start = seg_end;
+                }
+                total.saturating_add(flow_rate.saturating_mul(overlap(seg_start, u64::MAX)))
+            }
+        }
+    }
+
+    /// Per-second rate the curve applies at `at`; stepped streams report
+    /// their average rate.
+    fn rate_at(&self, flow_rate: u128, start_time: u64, at: u64) -> u128 {
+        let Self::PiecewiseLinear { segments } = self else {
+            return flow_rate;
+        };
+        let mut seg_end = start_time;
+        for seg in segments {
+            seg_end = seg_end.saturating_add(seg.duration);
+            if at < seg_end {
+                return flow_rate.saturating_mul(seg.rate_bps as u128) / BPS_DENOMINATOR;
+            }
+        }
+        flow_rate
+    }
+
+    /// Seconds of accrual the buffer must cover. A stepped stream releases
+    /// a whole period at once, so its buffer must hold the next tranche.
+    fn buffer_seconds(&self, min_buffer_seconds: u64) -> u64 {
+        match self {
+            Self::Stepped { period } => (*period).max(min_buffer_seconds),
+            _ => min_buffer_seconds,
+        }
+    }
+
+    /// Deposit a new open-ended stream needs so that it is not liquidatable
+    /// at any point of the curve.
+    fn min_deposit(&self, flow_rate: u128, min_buffer_seconds: u64) -> u128 {
+        self.peak_rate(flow_rate)
+            .saturating_mul(self.buffer_seconds(min_buffer_seconds) as u128)
+    }
+
+    /// Highest per-second rate the curve ever applies to `flow_rate`.
+    fn peak_rate(&self, flow_rate: u128) -> u128 {
+        let Self::PiecewiseLinear { segments } = self else {
+            return flow_rate;
+        };
+        segments
+            .iter()
+            .map(|seg| flow_rate.saturating_mul(seg.rate_bps as u128) / BPS_DENOMINATOR)
+            .fold(flow_rate, u128::max)
+    }
+
+    /// First time after `at` where `rate_at` switches to another segment.
+    fn next_rate_change(&self, start_time: u64, at: u64) -> Option<u64> {
+        let Self::PiecewiseLinear { segments } = self else {
+            return None;
+        };
+        let mut seg_end = start_time;
+        for seg in segments {
+            seg_end = seg_end.saturating_add(seg.duration);
+            if seg_end > at {
+                return Some(seg_end);
+            }
+        }
+        None
+    }
+}
+
+/// Share of `total` released by `at` when it is spread evenly from `start`
+/// to `end`: `total * elapsed / duration`, rounded down and capped at `total`.
+/// No per-second rate is derived, so any total fits any window exactly.
+fn released_pro_rata(total: u128, start: u64, end: u64, at: u64) -> u128 {
+    if at >= end {
+        return total;
+    }
+    if at <= start {
+        return 0;
+    }
+    let (elapsed, duration) = ((at - start) as u128, (end - start) as u128);
+    // `total * elapsed` could overflow; split `total` by the duration so every
+    // product stays in range while the floor is unchanged.
+    (total / duration) * elapsed + (total % duration) * elapsed / duration
+}
+
+/// A flow-rate change queued to take effect at `at`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct RateChange {
+    pub at: u64,
+    pub new_rate: u128,
+}
+
+/// Self-scheduled withdrawals to the receiver every `interval_blocks`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct AutoPayout {
+    pub interval_blocks: u32,
+    pub next_payout_block: u32,
+    /// The delayed message that will run the next payout; any other
+    /// `process_auto_payout` message for the stream is ignored.
+    pub pending_message: MessageId,
+}
+
+/// A receiver program's opt-in to stream notifications: StreamCore sends
+/// `OnStreamFunds` and `OnStreamStopped` to its `service` with `gas_limit`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct ReceiverHook {
+    pub service: String,
+    pub gas_limit: u64,
+}
+
+/// Who may stop a stream before it ends, fixed when the stream is opened.
+/// With both flags off the stream is non-cancelable.
+#[derive(Debug, Clone, Copy, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct CancelRights {
+    pub cancelable_by_sender: bool,
+    pub cancelable_by_receiver: bool,
+}
+
+impl CancelRights {
+    pub const SENDER_ONLY: Self = Self {
+        cancelable_by_sender: true,
+        cancelable_by_receiver: false,
+    };
 }
 
 #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
@@ -37,9 +384,26 @@ pub struct Stream {
     pub id: StreamId,
     pub sender: ActorId,
     pub receiver: ActorId,
+    /// SplitsRouter group that every withdrawal is distributed to; the
+    /// receiver is then the group's owner.
+    pub split_group: Option<u64>,
     pub token: ActorId,
+    /// Tokens per second. Fixed-duration streams accrue `deposited` pro rata
+    /// over their window instead; for them this is only the average rate,
+    /// rounded down.
     pub flow_rate: u128,
+    /// Accrual begins here; may lie in the future for delayed-start streams.
     pub start_time: u64,
+    /// Nothing is withdrawable before this time; afterwards everything
+    /// accrued since `start_time` unlocks at once.
+    pub cliff_time: Option<u64>,
+    /// Fixed-duration streams pay out exactly `deposited` by this time.
+    pub end_time: Option<u64>,
+    pub curve: ReleaseCurve,
+    pub cancel_rights: CancelRights,
+    /// Queued rate changes in time order, applied as the stream is settled.
+    pub rate_changes: Vec<RateChange>,
+    pub auto_payout: Option<AutoPayout>,
     pub last_update: u64,
     pub deposited: u128,
     pub withdrawn: u128,
@@ -47,12 +411,376 @@ pub struct Stream {
     pub status: StreamStatus,
 }
 
+/// A stream's accrual state as of `at`, recorded whenever its rate, deposit
+/// or status changes. Everything streamed until the next checkpoint follows
+/// from these fields and the stream's fixed schedule.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub at: u64,
+    pub status: StreamStatus,
+    pub flow_rate: u128,
+    pub rate_changes: Vec<RateChange>,
+    pub deposited: u128,
+    pub streamed: u128,
+    pub last_update: u64,
+}
+
+/// What is kept of a stream once it is archived: it ended and every token it
+/// streamed was withdrawn, so these fields are final.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct ArchivedStream {
+    pub id: StreamId,
+    pub sender: ActorId,
+    pub receiver: ActorId,
+    pub token: ActorId,
+    pub split_group: Option<u64>,
+    pub status: StreamStatus,
+    pub start_time: u64,
+    pub end_time: Option<u64>,
+    /// Kept with `start_time` and `end_time` so the stream's checkpoints can
+    /// still be replayed by `streamed_at`.
+    pub curve: ReleaseCurve,
+    /// When the stream was stopped or completed.
+    pub closed_at: u64,
+    /// Total streamed, all of it withdrawn.
+    pub streamed: u128,
+}
+
+/// Narrows paginated stream queries; `None` fields match every stream.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct StreamFilter {
+    pub status: Option<StreamStatus>,
+    pub token: Option<ActorId>,
+}
+
+impl StreamFilter {
+    fn matches(&self, stream: &Stream) -> bool {
+        self.status.as_ref().is_none_or(|status| *status == stream.status)
+            && self.token.is_none_or(|token| token == stream.token)
+    }
+}
+
+/// One entry of `create_streams_batch`; mirrors `create_stream`'s arguments
+/// plus the stream's cancellation rights.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct StreamParams {
+    pub receiver: ActorId,
+    pub token: ActorId,
+    pub flow_rate: u128,
+    pub initial_deposit: u128,
+    pub cancel_rights: CancelRights,
+}
+
+/// Everything a stream can be opened with besides its receiver, token, rate
+/// and deposit, for `create_stream_with_options`. `StreamOptions::default()`
+/// describes a plain `create_stream` stream.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Account funding the stream from its vault balance; the caller needs
+    /// its `CreateStream` scope. The caller itself if `None`.
+    pub sender: Option<ActorId>,
+    /// SplitsRouter group to pay instead of a single receiver; the group's
+    /// owner becomes the receiver.
+    pub split_group: Option<u64>,
+    /// Now if `None`.
+    pub start_time: Option<u64>,
+    pub cliff_time: Option<u64>,
+    /// Makes the stream pay exactly its deposit by `end_time`.
+    pub end_time: Option<u64>,
+    pub curve: ReleaseCurve,
+    pub cancel_rights: CancelRights,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            sender: None,
+            split_group: None,
+            start_time: None,
+            cliff_time: None,
+            end_time: None,
+            curve: ReleaseCurve::Linear,
+            cancel_rights: CancelRights::SENDER_ONLY,
+        }
+    }
+}
+
+impl StreamOptions {
+    fn schedule(&self, now: u64) -> Result<StreamSchedule, StreamError> {
+        // Fixed-duration streams release pro rata and ignore the curve.
+        if !self.curve.is_valid()
+            || (self.end_time.is_some() && self.curve != ReleaseCurve::Linear)
+        {
+            return Err(StreamError::InvalidCurve);
+        }
+        let schedule = StreamSchedule {
+            start_time: self.start_time.unwrap_or(now),
+            cliff_time: self.cliff_time,
+            end_time: self.end_time,
+            curve: self.curve.clone(),
+            cancel_rights: self.cancel_rights,
+            split_group: self.split_group,
+        };
+        if !schedule.is_valid(now) {
+            return Err(StreamError::InvalidSchedule);
+        }
+        Ok(schedule)
+    }
+}
+
+/// Mirror of `token_vault::StreamAllocation`, one stream's share of a
+/// batched vault call.
+#[derive(Debug, Clone, Encode)]
+struct StreamAllocation {
+    stream_id: StreamId,
+    token: ActorId,
+    amount: u128,
+}
+
+/// One recipient's part of a withdrawal distributed to a split group;
+/// mirrors `splits_router::DistributionPreview`.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub struct SplitShare {
+    pub address: ActorId,
+    pub amount: u128,
+}
+
+/// Mirror of `splits_router::SplitGroup`, decoded from `GetSplitGroup`.
+#[derive(Decode)]
+#[allow(dead_code)]
+struct SplitGroup {
+    id: u64,
+    owner: ActorId,
+    recipients: Vec<(ActorId, u32)>,
+    total_weight: u32,
+    created_at: u64,
+    updated_at: u64,
+}
+
+/// Fields overwritten when a stream is closed, kept so a rejected vault call
+/// can put the stream back as it was.
+struct StreamSnapshot {
+    status: StreamStatus,
+    flow_rate: u128,
+    deposited: u128,
+    streamed: u128,
+    rate_changes: Vec<RateChange>,
+}
+
+/// Timing, release shape, cancellation terms and split group chosen when a
+/// stream is opened.
+struct StreamSchedule {
+    start_time: u64,
+    cliff_time: Option<u64>,
+    end_time: Option<u64>,
+    curve: ReleaseCurve,
+    cancel_rights: CancelRights,
+    split_group: Option<u64>,
+}
+
+impl StreamSchedule {
+    fn starting_now(now: u64) -> Self {
+        Self {
+            start_time: now,
+            cliff_time: None,
+            end_time: None,
+            curve: ReleaseCurve::Linear,
+            cancel_rights: CancelRights::SENDER_ONLY,
+            split_group: None,
+        }
+    }
+
+    fn is_valid(&self, now: u64) -> bool {
+        let end_ok = self.end_time.is_none_or(|end| end > self.start_time);
+        let cliff_ok = self.cliff_time.is_none_or(|cliff| {
+            cliff >= self.start_time && self.end_time.is_none_or(|end| cliff <= end)
+        });
+        self.start_time >= now && end_ok && cliff_ok && self.curve.is_valid()
+    }
+}
+
+/// Combined per-second rates of an account's active streams in one token.
+#[derive(Debug, Clone, Default, Encode, Decode, TypeInfo)]
+pub struct FlowRates {
+    pub outgoing_rate: u128,
+    pub incoming_rate: u128,
+}
+
+/// An account's real-time position in one token across all its streams.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct AccountFlow {
+    pub outgoing_rate: u128,
+    pub incoming_rate: u128,
+    /// `incoming_rate - outgoing_rate`; negative when the account pays out more.
+    pub net_flow_rate: i128,
+    /// Withdrawable right now across all streams the account receives.
+    pub total_withdrawable: u128,
+}
+
 #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
 pub struct Config {
     pub admin: ActorId,
+    /// Proposed by the admin; becomes admin once it calls `accept_admin`.
+    pub pending_admin: Option<ActorId>,
+    /// Default minimum buffer; tokens may override it.
     pub min_buffer_seconds: u64,
     pub next_stream_id: StreamId,
     pub token_vault: ActorId,
+    pub permission_manager: ActorId,
+    /// SplitsRouter used by split-group streams; must list this program as a
+    /// distributor.
+    pub splits_router: ActorId,
+    pub liquidation_reward_bps: u16,
+    /// Gas attached to each in-program liquidation check; 0 disables the
+    /// keeper.
+    pub keeper_gas: u64,
+    /// Emergency switch blocking new streams and rate increases; withdrawals,
+    /// stops and liquidations keep working.
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Copy, Encode, Decode, TypeInfo, PartialEq, Eq)]
+pub enum StreamError {
+    NotFound,
+    Unauthorized,
+    InvalidStatus,
+    ZeroFlowRate,
+    ZeroAmount,
+    SenderIsReceiver,
+    InsufficientBuffer,
+    NothingToWithdraw,
+    NotLiquidatable,
+    VaultCallFailed,
+    VaultRejected,
+    PermissionCheckFailed,
+    InvalidConfig,
+    InvalidSchedule,
+    FixedDuration,
+    InvalidCurve,
+    InvalidReceiver,
+    InvalidBatch,
+    ExceedsWithdrawable,
+    NotCancelable,
+    ProtocolPaused,
+    GasReservationFailed,
+    SplitsCallFailed,
+    InvalidHook,
+    NotArchivable,
+}
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub enum StreamEvent {
+    StreamCreated {
+        id: StreamId,
+        sender: ActorId,
+        receiver: ActorId,
+        split_group: Option<u64>,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        start_time: u64,
+        cliff_time: Option<u64>,
+        end_time: Option<u64>,
+    },
+    FlowRateUpdated {
+        id: StreamId,
+        old_flow_rate: u128,
+        new_flow_rate: u128,
+        streamed: u128,
+        updated_at: u64,
+    },
+    RateChangeScheduled {
+        id: StreamId,
+        at: u64,
+        new_rate: u128,
+    },
+    RateChangeCanceled {
+        id: StreamId,
+        at: u64,
+    },
+    AutoPayoutEnabled {
+        id: StreamId,
+        interval_blocks: u32,
+        next_payout_block: u32,
+    },
+    AutoPayoutCanceled {
+        id: StreamId,
+    },
+    Paused {
+        id: StreamId,
+        streamed: u128,
+        paused_at: u64,
+    },
+    Resumed {
+        id: StreamId,
+        resumed_at: u64,
+    },
+    Stopped {
+        id: StreamId,
+        streamed: u128,
+        withdrawn: u128,
+        refunded: u128,
+        stopped_at: u64,
+    },
+    Completed {
+        id: StreamId,
+        streamed: u128,
+        completed_at: u64,
+    },
+    Deposited {
+        id: StreamId,
+        sender: ActorId,
+        amount: u128,
+        new_deposited: u128,
+    },
+    Withdrawn {
+        id: StreamId,
+        receiver: ActorId,
+        destination: ActorId,
+        amount: u128,
+        fee: u128,
+        /// Per-recipient amounts when the stream pays a split group.
+        distribution: Vec<SplitShare>,
+        timestamp: u64,
+    },
+    ReceiverApproved {
+        id: StreamId,
+        receiver: ActorId,
+        operator: Option<ActorId>,
+    },
+    ReceiverTransferred {
+        id: StreamId,
+        from: ActorId,
+        to: ActorId,
+        settled: u128,
+        timestamp: u64,
+    },
+    Liquidated {
+        id: StreamId,
+        liquidator: ActorId,
+        streamed: u128,
+        refunded: u128,
+        reward: u128,
+        liquidated_at: u64,
+    },
+    ReceiverHookSet {
+        receiver: ActorId,
+        hook: Option<ReceiverHook>,
+    },
+    ReceiverHookFailed {
+        id: StreamId,
+        receiver: ActorId,
+        method: String,
+    },
+    StreamArchived {
+        id: StreamId,
+        streamed: u128,
+        archived_at: u64,
+    },
 }
 
 // ---------------------------------------------------------------------------
@@ -66,6 +794,23 @@ pub struct StreamCoreState {
     pub streams: BTreeMap<StreamId, Stream>,
     pub sender_streams: BTreeMap<ActorId, Vec<StreamId>>,
     pub receiver_streams: BTreeMap<ActorId, Vec<StreamId>>,
+    /// Operator allowed to move a stream's receiver rights, per stream.
+    pub receiver_approvals: BTreeMap<StreamId, ActorId>,
+    /// Protocol fee in basis points charged on withdrawals, per token.
+    pub token_fee_bps: BTreeMap<ActorId, u16>,
+    /// Per-token overrides of `config.min_buffer_seconds`.
+    pub token_min_buffers: BTreeMap<ActorId, u64>,
+    /// Pending keeper check per stream; older checks still in flight are ignored.
+    pub keeper_checks: BTreeMap<StreamId, MessageId>,
+    /// Rates of active streams per `(account, token)`.
+    pub account_flows: BTreeMap<(ActorId, ActorId), FlowRates>,
+    /// Receivers that opted into stream notifications.
+    pub receiver_hooks: BTreeMap<ActorId, ReceiverHook>,
+    /// Accrual history per stream, in time order; kept when a stream is
+    /// archived.
+    pub checkpoints: BTreeMap<StreamId, Vec<Checkpoint>>,
+    /// Summaries of pruned streams, no longer in `streams` or any index.
+    pub archived: BTreeMap<StreamId, ArchivedStream>,
     pub active_count: u64,
 }
 
@@ -74,13 +819,27 @@ impl StreamCoreState {
         Self {
             config: Config {
                 admin,
+                pending_admin: None,
                 min_buffer_seconds,
                 next_stream_id: 1,
                 token_vault: ActorId::zero(),
+                permission_manager: ActorId::zero(),
+                splits_router: ActorId::zero(),
+                liquidation_reward_bps: 0,
+                keeper_gas: 0,
+                paused: false,
             },
             streams: BTreeMap::new(),
             sender_streams: BTreeMap::new(),
             receiver_streams: BTreeMap::new(),
+            receiver_approvals: BTreeMap::new(),
+            token_fee_bps: BTreeMap::new(),
+            token_min_buffers: BTreeMap::new(),
+            keeper_checks: BTreeMap::new(),
+            account_flows: BTreeMap::new(),
+            receiver_hooks: BTreeMap::new(),
+            checkpoints: BTreeMap::new(),
+            archived: BTreeMap::new(),
             active_count: 0,
         }
     }
@@ -89,12 +848,258 @@ impl StreamCoreState {
         unsafe { STATE.as_mut().expect("State not initialized") }
     }
 
+    fn min_buffer_seconds(&self, token: ActorId) -> u64 {
+        self.token_min_buffers
+            .get(&token)
+            .copied()
+            .unwrap_or(self.config.min_buffer_seconds)
+    }
+
+    /// Share of a withdrawal of `amount` kept as protocol fee.
+    fn protocol_fee(&self, token: ActorId, amount: u128) -> u128 {
+        let fee_bps = self.token_fee_bps.get(&token).copied().unwrap_or(0);
+        amount.saturating_mul(fee_bps as u128) / BPS_DENOMINATOR
+    }
+
+    fn ensure_not_paused(&self) -> Result<(), StreamError> {
+        if self.config.paused {
+            return Err(StreamError::ProtocolPaused);
+        }
+        Ok(())
+    }
+
+    fn move_receiver_index(&mut self, stream_id: StreamId, from: ActorId, to: ActorId) {
+        if let Some(ids) = self.receiver_streams.get_mut(&from) {
+            ids.retain(|&id| id != stream_id);
+        }
+        self.receiver_streams.entry(to).or_default().push(stream_id);
+    }
+
+    /// Counts an active stream's rate towards its sender's outgoing and its
+    /// receiver's incoming flow.
+    fn add_flow(flows: &mut BTreeMap<(ActorId, ActorId), FlowRates>, stream: &Stream) {
+        let outgoing = flows.entry((stream.sender, stream.token)).or_default();
+        outgoing.outgoing_rate = outgoing.outgoing_rate.saturating_add(stream.flow_rate);
+        let incoming = flows.entry((stream.receiver, stream.token)).or_default();
+        incoming.incoming_rate = incoming.incoming_rate.saturating_add(stream.flow_rate);
+    }
+
+    /// Reverses `add_flow` when a stream stops being active or changes rate.
+    fn remove_flow(flows: &mut BTreeMap<(ActorId, ActorId), FlowRates>, stream: &Stream) {
+        for (account, outgoing) in [(stream.sender, true), (stream.receiver, false)] {
+            let key = (account, stream.token);
+            let Some(rates) = flows.get_mut(&key) else {
+                continue;
+            };
+            let rate = if outgoing {
+                &mut rates.outgoing_rate
+            } else {
+                &mut rates.incoming_rate
+            };
+            *rate = rate.saturating_sub(stream.flow_rate);
+            if rates.outgoing_rate == 0 && rates.incoming_rate == 0 {
+                flows.remove(&key);
+            }
+        }
+    }
+
+    /// Settles and stops a stream, forfeiting accrual before a cliff since it
+    /// has not vested. Returns the snapshot to reopen from and the unstreamed
+    /// buffer, which is no longer owed to the receiver.
+    fn close_stream(&mut self, stream_id: StreamId, now: u64) -> Option<(StreamSnapshot, u128)> {
+        let stream = self.streams.get_mut(&stream_id)?;
+        if stream.status == StreamStatus::Active {
+            Self::remove_flow(&mut self.account_flows, stream);
+        }
+        Self::settle(stream, now);
+        let snapshot = StreamSnapshot {
+            status: stream.status.clone(),
+            flow_rate: stream.flow_rate,
+            deposited: stream.deposited,
+            streamed: stream.streamed,
+            rate_changes: core::mem::take(&mut stream.rate_changes),
+        };
+        let buffer = Self::closing_buffer(stream, now);
+        if Self::before_cliff(stream, now) {
+            stream.streamed = stream.withdrawn;
+        }
+
+        stream.status = StreamStatus::Stopped;
+        stream.flow_rate = 0;
+        stream.deposited = stream.streamed;
+        if snapshot.status == StreamStatus::Active {
+            self.active_count = self.active_count.saturating_sub(1);
+        }
+        self.record_checkpoint(stream_id, now);
+        Some((snapshot, buffer))
+    }
+
+    /// Undoes `close_stream`; `paid` is what already left the stream's
+    /// allocation and is not restored to its deposit.
+    fn reopen_stream(&mut self, stream_id: StreamId, snapshot: &StreamSnapshot, paid: u128) {
+        // A stream pruned while the vault call was pending stays archived.
+        let Some(stream) = self.streams.get_mut(&stream_id) else {
+            return;
+        };
+        stream.status = snapshot.status.clone();
+        stream.flow_rate = snapshot.flow_rate;
+        stream.deposited = snapshot.deposited.saturating_sub(paid);
+        stream.streamed = snapshot.streamed;
+        stream.rate_changes = snapshot.rate_changes.clone();
+        if snapshot.status == StreamStatus::Active {
+            Self::add_flow(&mut self.account_flows, stream);
+            self.active_count += 1;
+        }
+        // Stopped streams take no other command, so the last checkpoint is
+        // still the one `close_stream` recorded.
+        if let Some(checkpoints) = self.checkpoints.get_mut(&stream_id) {
+            checkpoints.pop();
+        }
+    }
+
+    /// A stream can be archived once it is stopped or completed and
+    /// everything it streamed has been withdrawn.
+    fn is_archivable(stream: &Stream, now: u64) -> bool {
+        matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed)
+            && Self::withdrawable_balance(stream, now) == 0
+    }
+
+    /// Collapses a finished stream into its summary and drops it and its
+    /// per-stream entries from every index. Its checkpoints are kept as the
+    /// stream's payment history.
+    fn archive_stream(&mut self, stream_id: StreamId) -> Option<&ArchivedStream> {
+        let stream = self.streams.remove(&stream_id)?;
+        let closed_at = self
+            .checkpoints
+            .get(&stream_id)
+            .and_then(|checkpoints| checkpoints.last().map(|checkpoint| checkpoint.at))
+            .unwrap_or(stream.last_update);
+
+        for (index, account) in [
+            (&mut self.sender_streams, stream.sender),
+            (&mut self.receiver_streams, stream.receiver),
+        ] {
+            if let Some(ids) = index.get_mut(&account) {
+                ids.retain(|id| *id != stream_id);
+                if ids.is_empty() {
+                    index.remove(&account);
+                }
+            }
+        }
+        self.receiver_approvals.remove(&stream_id);
+        self.keeper_checks.remove(&stream_id);
+
+        let summary = ArchivedStream {
+            id: stream_id,
+            sender: stream.sender,
+            receiver: stream.receiver,
+            token: stream.token,
+            split_group: stream.split_group,
+            status: stream.status,
+            start_time: stream.start_time,
+            end_time: stream.end_time,
+            curve: stream.curve,
+            closed_at,
+            streamed: stream.streamed,
+        };
+        Some(self.archived.entry(stream_id).or_insert(summary))
+    }
+
+    /// Appends the stream's current accrual state to its history.
+    fn record_checkpoint(&mut self, stream_id: StreamId, now: u64) {
+        let Some(stream) = self.streams.get(&stream_id) else {
+            return;
+        };
+        let checkpoint = Checkpoint {
+            at: now,
+            status: stream.status.clone(),
+            flow_rate: stream.flow_rate,
+            rate_changes: stream.rate_changes.clone(),
+            deposited: stream.deposited,
+            streamed: stream.streamed,
+            last_update: stream.last_update,
+        };
+        self.checkpoints.entry(stream_id).or_default().push(checkpoint);
+    }
+
+    /// Total streamed by `at`, replayed from the last checkpoint at or before
+    /// it. Zero before the stream was created; `None` for unknown ids.
+    fn streamed_at(&self, stream_id: StreamId, at: u64) -> Option<u128> {
+        let (start_time, end_time, curve) = match self.streams.get(&stream_id) {
+            Some(stream) => (stream.start_time, stream.end_time, &stream.curve),
+            None => {
+                let archived = self.archived.get(&stream_id)?;
+                (archived.start_time, archived.end_time, &archived.curve)
+            }
+        };
+        let checkpoints = self.checkpoints.get(&stream_id)?;
+        let index = checkpoints.partition_point(|checkpoint| checkpoint.at <= at);
+        let Some(checkpoint) = index.checked_sub(1).map(|index| &checkpoints[index]) else {
+            return Some(0);
+        };
+        // Only the accrual fields matter for the replay.
+        let past = Stream {
+            id: stream_id,
+            sender: ActorId::zero(),
+            receiver: ActorId::zero(),
+            split_group: None,
+            token: ActorId::zero(),
+            flow_rate: checkpoint.flow_rate,
+            start_time,
+            cliff_time: None,
+            end_time,
+            curve: curve.clone(),
+            cancel_rights: CancelRights::SENDER_ONLY,
+            rate_changes: checkpoint.rate_changes.clone(),
+            auto_payout: None,
+            last_update: checkpoint.last_update,
+            deposited: checkpoint.deposited,
+            withdrawn: 0,
+            streamed: checkpoint.streamed,
+            status: checkpoint.status.clone(),
+        };
+        Some(Self::total_streamed(&past, at).min(past.deposited))
+    }
+
     fn accrued_since_last_update(stream: &Stream, now: u64) -> u128 {
         if stream.status != StreamStatus::Active || now <= stream.last_update {
             return 0;
         }
-        let elapsed = (now - stream.last_update) as u128;
-        stream.flow_rate.saturating_mul(elapsed)
+        if let Some(end_time) = stream.end_time {
+            let due = released_pro_rata(stream.deposited, stream.start_time, end_time, now);
+            return due.saturating_sub(stream.streamed);
+        }
+        // Queued rate changes split the interval into stretches at each rate.
+        let mut total: u128 = 0;
+        let mut from = stream.last_update;
+        let mut rate = stream.flow_rate;
+        for change in stream.rate_changes.iter().take_while(|change| change.at < now) {
+            if change.at > from {
+                let part = stream.curve.accrued(rate, stream.start_time, from, change.at);
+                total = total.saturating_add(part);
+                from = change.at;
+            }
+            rate = change.new_rate;
+        }
+        total.saturating_add(stream.curve.accrued(rate, stream.start_time, from, now))
+    }
+
+    /// Base flow rate in effect at `now`, counting queued changes already due.
+    fn flow_rate_at(stream: &Stream, now: u64) -> u128 {
+        stream
+            .rate_changes
+            .iter()
+            .take_while(|change| change.at <= now)
+            .last()
+            .map_or(stream.flow_rate, |change| change.new_rate)
+    }
+
+    fn before_cliff(stream: &Stream, now: u64) -> bool {
+        stream.cliff_time.is_some_and(|cliff| now < cliff)
+    }
+
+    fn has_ended(stream: &Stream, now: u64) -> bool {
+        stream.end_time.is_some_and(|end| now >= end)
     }
 
     fn total_streamed(stream: &Stream, now: u64) -> u128 {
@@ -104,6 +1109,9 @@ impl StreamCoreState {
     }
 
     fn withdrawable_balance(stream: &Stream, now: u64) -> u128 {
+        if Self::before_cliff(stream, now) {
+            return 0;
+        }
         let total = Self::total_streamed(stream, now);
         let capped = total.min(stream.deposited);
         capped.saturating_sub(stream.withdrawn)
@@ -121,256 +1129,2078 @@ impl StreamCoreState {
             if stream.streamed > stream.deposited {
                 stream.streamed = stream.deposited;
             }
-            stream.last_update = now;
+            // `last_update` starts at a future `start_time` for delayed
+            // streams and must not move back before it.
+            stream.last_update = now.max(stream.last_update);
         }
+        // Due changes take effect even while paused, so resuming picks up
+        // the agreed rate.
+        stream.flow_rate = Self::flow_rate_at(stream, now);
+        stream.rate_changes.retain(|change| change.at > now);
     }
 
-    fn should_liquidate(stream: &Stream, now: u64, min_buffer_seconds: u64) -> bool {
-        if stream.status != StreamStatus::Active || stream.flow_rate == 0 {
-            return false;
+    /// Per-second rate an active stream is actually paying at `now`: 0 before
+    /// it starts or once a fixed-duration stream has ended, otherwise the
+    /// curve's rate with due queued changes applied.
+    fn current_rate(stream: &Stream, now: u64) -> u128 {
+        if now < stream.start_time || Self::has_ended(stream, now) {
+            return 0;
         }
-        let remaining = Self::remaining_buffer(stream, now);
-        let min_buffer = stream.flow_rate.saturating_mul(min_buffer_seconds as u128);
-        remaining < min_buffer
+        let base_rate = Self::flow_rate_at(stream, now);
+        stream.curve.rate_at(base_rate, stream.start_time, now)
     }
-}
 
-// ---------------------------------------------------------------------------
-// Program (constructor)
-// ---------------------------------------------------------------------------
-
-pub struct StreamCoreProgram;
+    /// `account_flows` entry for `account` in `token` as of `now`. The stored
+    /// totals hold each active stream's base `flow_rate` until it next
+    /// settles, so every stream is swapped for its `current_rate` here.
+    fn account_rates(&self, account: ActorId, token: ActorId, now: u64) -> FlowRates {
+        let mut rates = self
+            .account_flows
+            .get(&(account, token))
+            .cloned()
+            .unwrap_or_default();
+        for (index, outgoing) in [(&self.sender_streams, true), (&self.receiver_streams, false)] {
+            let streams = index
+                .get(&account)
+                .into_iter()
+                .flatten()
+                .filter_map(|id| self.streams.get(id))
+                .filter(|stream| stream.token == token && stream.status == StreamStatus::Active);
+            for stream in streams {
+                let rate = if outgoing {
+                    &mut rates.outgoing_rate
+                } else {
+                    &mut rates.incoming_rate
+                };
+                *rate = rate
+                    .saturating_sub(stream.flow_rate)
+                    .saturating_add(Self::current_rate(stream, now));
+            }
+        }
+        rates
+    }
 
-#[program]
-impl StreamCoreProgram {
-    pub fn new() -> Self {
-        let admin = msg::source();
-        unsafe {
-            STATE = Some(StreamCoreState::new(admin, 3600));
+    /// `settle` for a stored stream, keeping `account_flows` in step with
+    /// any queued rate change it applies.
+    fn settle_tracked(
+        flows: &mut BTreeMap<(ActorId, ActorId), FlowRates>,
+        stream: &mut Stream,
+        now: u64,
+    ) {
+        let active = stream.status == StreamStatus::Active;
+        if active {
+            Self::remove_flow(flows, stream);
+        }
+        Self::settle(stream, now);
+        if active {
+            Self::add_flow(flows, stream);
         }
-        Self
     }
 
-    pub fn stream_service(&self) -> StreamService {
-        StreamService
+    /// The stream as queries report it: fixed-duration streams past their end
+    /// show as completed even before the next command settles them.
+    fn view(stream: &Stream, now: u64) -> Stream {
+        let mut stream = stream.clone();
+        if stream.status == StreamStatus::Active && Self::has_ended(&stream, now) {
+            Self::settle(&mut stream, now);
+            stream.status = StreamStatus::Completed;
+        }
+        stream
     }
-}
 
-// ---------------------------------------------------------------------------
-// Service
-// ---------------------------------------------------------------------------
+    /// Skips `offset` streams matching `filter` and returns up to `limit` of
+    /// the following ones, in id order.
+    fn page(
+        &self,
+        ids: impl Iterator<Item = StreamId>,
+        filter: &StreamFilter,
+        offset: u32,
+        limit: u32,
+        now: u64,
+    ) -> Vec<Stream> {
+        ids.filter_map(|id| self.streams.get(&id))
+            .map(|stream| Self::view(stream, now))
+            .filter(|stream| filter.matches(stream))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    }
 
-pub struct StreamService;
+    /// What stopping the stream at `now` takes back from the receiver: the
+    /// unstreamed buffer plus, before a cliff, the unvested accrual.
+    fn closing_buffer(stream: &Stream, now: u64) -> u128 {
+        let kept = if Self::before_cliff(stream, now) {
+            stream.withdrawn
+        } else {
+            Self::total_streamed(stream, now).min(stream.deposited)
+        };
+        stream.deposited.saturating_sub(kept)
+    }
 
-impl StreamService {
-    pub fn new() -> Self {
-        Self
+    /// Liquidator's share of the `closing_buffer` a liquidation takes back.
+    fn liquidation_reward(buffer: u128, reward_bps: u16) -> u128 {
+        buffer.saturating_mul(reward_bps as u128) / BPS_DENOMINATOR
+    }
+
+    fn should_liquidate(stream: &Stream, now: u64, min_buffer_seconds: u64) -> bool {
+        // Fixed-duration streams are fully funded up front and never insolvent.
+        if stream.status != StreamStatus::Active
+            || stream.flow_rate == 0
+            || stream.end_time.is_some()
+        {
+            return false;
+        }
+        let remaining = Self::remaining_buffer(stream, now);
+        let base_rate = Self::flow_rate_at(stream, now);
+        let rate = stream.curve.rate_at(base_rate, stream.start_time, now);
+        let seconds = stream.curve.buffer_seconds(min_buffer_seconds);
+        let min_buffer = rate.saturating_mul(seconds as u128);
+        remaining < min_buffer
+    }
+
+    /// Seconds from `now` until the stream's buffer falls below the minimum at
+    /// its current rate, or `None` if it can never become liquidatable. The
+    /// estimate never reaches past the next queued rate change or curve
+    /// segment, where the rate and therefore the answer may change.
+    fn seconds_until_liquidatable(stream: &Stream, now: u64, min_buffer_seconds: u64) -> Option<u64> {
+        if stream.status != StreamStatus::Active || stream.end_time.is_some() {
+            return None;
+        }
+        let at = now.max(stream.start_time);
+        let base_rate = Self::flow_rate_at(stream, at);
+        let rate = stream.curve.rate_at(base_rate, stream.start_time, at);
+        if rate == 0 {
+            return None;
+        }
+        let remaining = Self::remaining_buffer(stream, now);
+        let buffer_seconds = stream.curve.buffer_seconds(min_buffer_seconds);
+        let min_buffer = rate.saturating_mul(buffer_seconds as u128);
+        let seconds = remaining.saturating_sub(min_buffer) / rate + 1;
+        let until = (at - now).saturating_add(seconds.try_into().unwrap_or(u64::MAX));
+
+        let next_change = stream
+            .rate_changes
+            .iter()
+            .map(|change| change.at)
+            .find(|change_at| *change_at > at)
+            .into_iter()
+            .chain(stream.curve.next_rate_change(stream.start_time, at))
+            .min();
+        Some(next_change.map_or(until, |change_at| until.min(change_at - now)))
+    }
+
+    /// Sends a delayed `process_keeper_check` to this program for when the
+    /// stream should become liquidatable, replacing any earlier check. Does
+    /// nothing if the keeper is disabled or the check cannot be sent; the
+    /// public `liquidate` remains available either way.
+    fn schedule_keeper_check(&mut self, stream_id: StreamId) {
+        self.send_keeper_check(stream_id, self.config.keeper_gas);
+    }
+
+    /// `schedule_keeper_check` with the check carrying `gas`.
+    fn send_keeper_check(&mut self, stream_id: StreamId, gas: u64) {
+        self.keeper_checks.remove(&stream_id);
+        if self.config.keeper_gas == 0 {
+            return;
+        }
+        let Some(stream) = self.streams.get(&stream_id) else {
+            return;
+        };
+        let now = exec::block_timestamp() / 1000;
+        let min_buffer_seconds = self.min_buffer_seconds(stream.token);
+        let Some(seconds) = Self::seconds_until_liquidatable(stream, now, min_buffer_seconds) else {
+            return;
+        };
+
+        let delay = seconds.div_ceil(BLOCK_TIME_SECONDS).try_into().unwrap_or(u32::MAX);
+        let payload = encode_call("StreamService", "ProcessKeeperCheck", stream_id);
+        if let Ok(message_id) =
+            gstd_msg::send_bytes_with_gas_delayed(exec::program_id(), payload, gas, 0, delay)
+        {
+            self.keeper_checks.insert(stream_id, message_id);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Program (constructor)
+// ---------------------------------------------------------------------------
+
+pub struct StreamCoreProgram;
+
+#[program]
+impl StreamCoreProgram {
+    pub fn new() -> Self {
+        let admin = msg::source();
+        unsafe {
+            STATE = Some(StreamCoreState::new(admin, 3600));
+        }
+        Self
+    }
+
+    pub fn stream_service(&self) -> StreamService {
+        StreamService
     }
 }
 
-#[service]
+// ---------------------------------------------------------------------------
+// Service
+// ---------------------------------------------------------------------------
+
+pub struct StreamService;
+
 impl StreamService {
-    // ---- Commands ----
+    pub fn new() -> Self {
+        Self
+    }
 
-    pub fn create_stream(
+    /// Passes when `caller` is `owner` itself, otherwise asks the configured
+    /// PermissionManager whether `owner` granted `caller` the given scope.
+    async fn authorize(
+        owner: ActorId,
+        caller: ActorId,
+        scope: PermissionScope,
+    ) -> Result<(), StreamError> {
+        if caller == owner {
+            return Ok(());
+        }
+        let permission_manager = StreamCoreState::get().config.permission_manager;
+        if permission_manager == ActorId::zero() {
+            return Err(StreamError::Unauthorized);
+        }
+        if query_permission(permission_manager, owner, caller, scope).await? {
+            Ok(())
+        } else {
+            Err(StreamError::Unauthorized)
+        }
+    }
+
+    fn check_stream_params(
+        sender: ActorId,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        end_time: Option<u64>,
+        curve: &ReleaseCurve,
+    ) -> Result<(), StreamError> {
+        if end_time.is_none() && flow_rate == 0 {
+            return Err(StreamError::ZeroFlowRate);
+        }
+        if end_time.is_some() && initial_deposit == 0 {
+            return Err(StreamError::ZeroAmount);
+        }
+        if sender == receiver {
+            return Err(StreamError::SenderIsReceiver);
+        }
+
+        let min_buffer_seconds = StreamCoreState::get().min_buffer_seconds(token);
+        // Liquidation measures the buffer at the curve's current rate, so a
+        // segment faster than `flow_rate` must be covered from the start.
+        let min_deposit = curve.min_deposit(flow_rate, min_buffer_seconds);
+        if end_time.is_none() && initial_deposit < min_deposit {
+            return Err(StreamError::InsufficientBuffer);
+        }
+        Ok(())
+    }
+
+    async fn open_stream(
         &mut self,
+        sender: ActorId,
         receiver: ActorId,
         token: ActorId,
         flow_rate: u128,
         initial_deposit: u128,
-    ) -> u64 {
+        schedule: StreamSchedule,
+    ) -> Result<u64, StreamError> {
+        StreamCoreState::get().ensure_not_paused()?;
+        Self::check_stream_params(
+            sender,
+            receiver,
+            token,
+            flow_rate,
+            initial_deposit,
+            schedule.end_time,
+            &schedule.curve,
+        )?;
+
+        // Reserve the id before awaiting so concurrent creations never collide;
+        // the stream itself is only recorded once the vault has locked the funds.
+        let state = StreamCoreState::get();
+        let id = state.config.next_stream_id;
+        state.config.next_stream_id += 1;
+
+        call_vault(
+            state.config.token_vault,
+            "AllocateToStream",
+            (sender, token, initial_deposit, id),
+        )
+        .await?;
+
+        self.record_stream(id, sender, receiver, token, flow_rate, initial_deposit, schedule);
+        Ok(id)
+    }
+
+    /// Stores a stream whose deposit the vault has already allocated.
+    #[allow(clippy::too_many_arguments)]
+    fn record_stream(
+        &mut self,
+        id: StreamId,
+        sender: ActorId,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        schedule: StreamSchedule,
+    ) {
+        let state = StreamCoreState::get();
+        let StreamSchedule {
+            start_time,
+            cliff_time,
+            end_time,
+            curve,
+            cancel_rights,
+            split_group,
+        } = schedule;
+
+        let stream = Stream {
+            id,
+            sender,
+            receiver,
+            split_group,
+            token,
+            flow_rate,
+            start_time,
+            cliff_time,
+            end_time,
+            curve,
+            cancel_rights,
+            rate_changes: Vec::new(),
+            auto_payout: None,
+            last_update: start_time,
+            deposited: initial_deposit,
+            withdrawn: 0,
+            streamed: 0,
+            status: StreamStatus::Active,
+        };
+
+        StreamCoreState::add_flow(&mut state.account_flows, &stream);
+        state.streams.insert(id, stream);
+        state.sender_streams.entry(sender).or_default().push(id);
+        state.receiver_streams.entry(receiver).or_default().push(id);
+        state.active_count += 1;
+        state.record_checkpoint(id, exec::block_timestamp() / 1000);
+        state.schedule_keeper_check(id);
+
+        self.notify_on(StreamEvent::StreamCreated {
+            id,
+            sender,
+            receiver,
+            split_group,
+            token,
+            flow_rate,
+            initial_deposit,
+            start_time,
+            cliff_time,
+            end_time,
+        })
+        .expect("Failed to emit StreamCreated");
+    }
+
+    /// Pays everything the current receiver has earned so far, then hands the
+    /// stream's future accrual to `to`. The reassignment is recorded before
+    /// awaiting the vault and undone if the payout is rejected.
+    async fn reassign_receiver(
+        &mut self,
+        stream_id: StreamId,
+        from: ActorId,
+        to: ActorId,
+        now: u64,
+    ) -> Result<u128, StreamError> {
+        self.complete_if_ended(stream_id, now);
+
+        let state = StreamCoreState::get();
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        if to == ActorId::zero() || to == from || stream.split_group.is_some() {
+            return Err(StreamError::InvalidReceiver);
+        }
+        if to == stream.sender {
+            return Err(StreamError::SenderIsReceiver);
+        }
+
+        StreamCoreState::settle_tracked(&mut state.account_flows, stream, now);
+        let settled = StreamCoreState::withdrawable_balance(stream, now);
+        stream.withdrawn = stream.withdrawn.saturating_add(settled);
+        let active = stream.status == StreamStatus::Active;
+        if active {
+            StreamCoreState::remove_flow(&mut state.account_flows, stream);
+        }
+        stream.receiver = to;
+        if active {
+            StreamCoreState::add_flow(&mut state.account_flows, stream);
+        }
+        let token = stream.token;
+        let approval = state.receiver_approvals.remove(&stream_id);
+        state.move_receiver_index(stream_id, from, to);
+        let fee = state.protocol_fee(token, settled);
+        let paid = settled - fee;
+
+        if settled > 0 {
+            if let Err(err) = call_vault_with_transfers(
+                state.config.token_vault,
+                "TransferToReceiver",
+                (token, from, paid, stream_id, fee),
+                vft_transfers(token),
+            )
+            .await
+            {
+                let state = StreamCoreState::get();
+                if let Some(stream) = state.streams.get_mut(&stream_id) {
+                    stream.withdrawn = stream.withdrawn.saturating_sub(settled);
+                    if stream.receiver == to {
+                        let active = stream.status == StreamStatus::Active;
+                        if active {
+                            StreamCoreState::remove_flow(&mut state.account_flows, stream);
+                        }
+                        stream.receiver = from;
+                        if active {
+                            StreamCoreState::add_flow(&mut state.account_flows, stream);
+                        }
+                        state.move_receiver_index(stream_id, to, from);
+                        if let Some(operator) = approval {
+                            state.receiver_approvals.insert(stream_id, operator);
+                        }
+                    }
+                }
+                return Err(err);
+            }
+        }
+
+        self.notify_on(StreamEvent::ReceiverTransferred {
+            id: stream_id,
+            from,
+            to,
+            settled: paid,
+            timestamp: now,
+        })
+        .expect("Failed to emit ReceiverTransferred");
+        if paid > 0 {
+            self.hook_stream_funds(stream_id, from, paid);
+        }
+
+        Ok(paid)
+    }
+
+    /// Pays `caller`'s earnings from a stream to `destination` (the receiver
+    /// itself if `None`), or into the receiver's vault balance when the
+    /// destination is the TokenVault.
+    async fn pay_out(
+        &mut self,
+        stream_id: StreamId,
+        caller: ActorId,
+        amount: Option<u128>,
+        destination: Option<ActorId>,
+    ) -> Result<u128, StreamError> {
         let state = StreamCoreState::get();
-        let sender = msg::source();
         let now = exec::block_timestamp() / 1000;
 
-        assert!(flow_rate > 0, "Flow rate must be > 0");
-        assert!(sender != receiver, "Sender and receiver must differ");
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if stream.receiver != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if stream.split_group.is_some() && destination.is_some() {
+            return Err(StreamError::InvalidReceiver);
+        }
+
+        self.complete_if_ended(stream_id, now);
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        StreamCoreState::settle_tracked(&mut state.account_flows, stream, now);
+        let withdrawable = StreamCoreState::withdrawable_balance(stream, now);
+        if withdrawable == 0 {
+            return Err(StreamError::NothingToWithdraw);
+        }
+        let amount = match amount {
+            None => withdrawable,
+            Some(0) => return Err(StreamError::ZeroAmount),
+            Some(amount) if amount > withdrawable => return Err(StreamError::ExceedsWithdrawable),
+            Some(amount) => amount,
+        };
 
-        let min_deposit = flow_rate.saturating_mul(state.config.min_buffer_seconds as u128);
-        assert!(
-            initial_deposit >= min_deposit,
-            "Initial deposit must cover minimum buffer"
+        // Book the withdrawal before awaiting so a concurrent call cannot claim
+        // the same funds, and undo it if the vault refuses the transfer.
+        stream.withdrawn = stream.withdrawn.saturating_add(amount);
+        let token = stream.token;
+        let split_group = stream.split_group;
+        let destination = destination.unwrap_or(caller);
+        let fee = state.protocol_fee(token, amount);
+        let paid = amount - fee;
+
+        let vault = state.config.token_vault;
+        let result = match split_group {
+            Some(group_id) => {
+                Self::distribute_to_group(stream_id, group_id, caller, token, paid, fee).await
+            }
+            None if destination == vault => {
+                call_vault(vault, "CreditFromStream", (caller, token, paid, stream_id, fee))
+                    .await
+                    .map(|()| Vec::new())
+            }
+            None => call_vault_with_transfers(
+                vault,
+                "TransferToReceiver",
+                (token, destination, paid, stream_id, fee),
+                vft_transfers(token),
+            )
+            .await
+            .map(|()| Vec::new()),
+        };
+        let distribution = match result {
+            Ok(distribution) => distribution,
+            Err(err) => {
+                if let Some(stream) = StreamCoreState::get().streams.get_mut(&stream_id) {
+                    stream.withdrawn = stream.withdrawn.saturating_sub(amount);
+                }
+                return Err(err);
+            }
+        };
+
+        self.notify_on(StreamEvent::Withdrawn {
+            id: stream_id,
+            receiver: caller,
+            destination,
+            amount: paid,
+            fee,
+            distribution,
+            timestamp: now,
+        })
+        .expect("Failed to emit Withdrawn");
+        self.hook_stream_funds(stream_id, caller, paid);
+
+        Ok(paid)
+    }
+
+    /// Sends `method` to `receiver`'s registered hook, if any. The message
+    /// is fire-and-forget with the hook's own gas limit, so a failing or
+    /// expensive hook can never block or revert the call that triggered it;
+    /// a hook that cannot even be sent is reported with `ReceiverHookFailed`.
+    fn call_receiver_hook(
+        &mut self,
+        stream_id: StreamId,
+        receiver: ActorId,
+        method: &str,
+        args: impl Encode,
+    ) {
+        let Some(hook) = StreamCoreState::get().receiver_hooks.get(&receiver) else {
+            return;
+        };
+        let payload = encode_call(&hook.service, method, args);
+        if gstd_msg::send_bytes_with_gas(receiver, payload, hook.gas_limit, 0).is_err() {
+            self.notify_on(StreamEvent::ReceiverHookFailed {
+                id: stream_id,
+                receiver,
+                method: method.into(),
+            })
+            .expect("Failed to emit ReceiverHookFailed");
+        }
+    }
+
+    /// Notifies `receiver` that `amount` was paid out of the stream.
+    fn hook_stream_funds(&mut self, stream_id: StreamId, receiver: ActorId, amount: u128) {
+        let Some(stream) = StreamCoreState::get().streams.get(&stream_id) else {
+            return;
+        };
+        let token = stream.token;
+        self.call_receiver_hook(stream_id, receiver, "OnStreamFunds", (stream_id, token, amount));
+    }
+
+    /// Notifies the receiver of a stopped stream how much it earned in total
+    /// and how much of that it has withdrawn.
+    fn hook_stream_stopped(&mut self, stream_id: StreamId) {
+        let Some(stream) = StreamCoreState::get().streams.get(&stream_id) else {
+            return;
+        };
+        let (receiver, token, streamed, withdrawn) =
+            (stream.receiver, stream.token, stream.streamed, stream.withdrawn);
+        self.call_receiver_hook(
+            stream_id,
+            receiver,
+            "OnStreamStopped",
+            (stream_id, token, streamed, withdrawn),
         );
+    }
 
-        let id = state.config.next_stream_id;
-        state.config.next_stream_id += 1;
+    /// Pays `amount` to a split group's recipients: the SplitsRouter works
+    /// out the shares, the vault pays each one from the stream's allocation,
+    /// and only then is the distribution added to the router's tally. If the
+    /// group has been deleted, `receiver` is paid instead and the stream
+    /// stops being a split stream so its funds never get stuck.
+    async fn distribute_to_group(
+        stream_id: StreamId,
+        group_id: u64,
+        receiver: ActorId,
+        token: ActorId,
+        amount: u128,
+        fee: u128,
+    ) -> Result<Vec<SplitShare>, StreamError> {
+        let config = &StreamCoreState::get().config;
+        let (splits_router, vault) = (config.splits_router, config.token_vault);
+        if splits_router == ActorId::zero() {
+            return Err(StreamError::InvalidConfig);
+        }
+
+        let shares: Vec<SplitShare> =
+            match call_splits(splits_router, "PreviewDistribution", (group_id, amount)).await {
+                Ok(shares) => shares,
+                Err(err) => {
+                    let group: Option<SplitGroup> =
+                        call_splits(splits_router, "GetSplitGroup", group_id).await?;
+                    if group.is_some() {
+                        return Err(err);
+                    }
+                    call_vault_with_transfers(
+                        vault,
+                        "TransferToReceiver",
+                        (token, receiver, amount, stream_id, fee),
+                        vft_transfers(token),
+                    )
+                    .await?;
+                    if let Some(stream) = StreamCoreState::get().streams.get_mut(&stream_id) {
+                        stream.split_group = None;
+                    }
+                    return Ok(Vec::new());
+                }
+            };
+        let total = shares
+            .iter()
+            .fold(0u128, |total, share| total.saturating_add(share.amount));
+        if total != amount {
+            return Err(StreamError::SplitsCallFailed);
+        }
+
+        let transfers = shares.iter().filter(|share| share.amount > 0).count() as u64;
+        call_vault_with_transfers(
+            vault,
+            "TransferToRecipients",
+            (token, &shares, stream_id, fee),
+            transfers * vft_transfers(token),
+        )
+        .await?;
+        // The recipients are paid either way; a failure here only leaves the
+        // router's running total short.
+        let _ = call_splits::<Vec<SplitShare>>(splits_router, "Distribute", (group_id, token, amount))
+            .await;
+        Ok(shares)
+    }
+
+    /// Moves a fixed-duration stream that has reached its `end_time` to
+    /// `Completed`, settling the full total as streamed.
+    fn complete_if_ended(&mut self, stream_id: StreamId, now: u64) {
+        let state = StreamCoreState::get();
+        let Some(stream) = state.streams.get_mut(&stream_id) else {
+            return;
+        };
+        if stream.status != StreamStatus::Active || !StreamCoreState::has_ended(stream, now) {
+            return;
+        }
+
+        StreamCoreState::remove_flow(&mut state.account_flows, stream);
+        StreamCoreState::settle(stream, now);
+        stream.status = StreamStatus::Completed;
+        let streamed = stream.streamed;
+        state.active_count = state.active_count.saturating_sub(1);
+        state.record_checkpoint(stream_id, now);
+
+        self.notify_on(StreamEvent::Completed {
+            id: stream_id,
+            streamed,
+            completed_at: now,
+        })
+        .expect("Failed to emit Completed");
+    }
+
+    /// Settles and stops a stream, releasing the unstreamed buffer back to the
+    /// sender's available vault balance. Earned-but-unwithdrawn funds stay
+    /// allocated to the stream so the receiver can still withdraw them;
+    /// anything accrued before a cliff is unvested and refunded as well.
+    ///
+    /// When `liquidator` is set, the configured liquidation reward is carved
+    /// out of the buffer and paid to them before the rest is refunded.
+    /// Returns the settled `(streamed, refunded, reward)` amounts; if the vault
+    /// rejects a call the stream is restored to its previous status.
+    async fn stop_and_refund(
+        stream_id: StreamId,
+        now: u64,
+        liquidator: Option<ActorId>,
+    ) -> Result<(u128, u128, u128), StreamError> {
+        let state = StreamCoreState::get();
+        let vault = state.config.token_vault;
+        let reward_bps = state.config.liquidation_reward_bps;
+        let (snapshot, buffer) = state
+            .close_stream(stream_id, now)
+            .ok_or(StreamError::NotFound)?;
+        let stream = &state.streams[&stream_id];
+        let (sender, token, streamed) = (stream.sender, stream.token, stream.streamed);
+
+        let reward = match liquidator {
+            Some(_) => StreamCoreState::liquidation_reward(buffer, reward_bps),
+            None => 0,
+        };
+        let refund = buffer.saturating_sub(reward);
+
+        // `paid` tracks how much has already left the stream's allocation so a
+        // failed follow-up call restores the stream without double-counting.
+        let restore = |paid: u128| {
+            StreamCoreState::get().reopen_stream(stream_id, &snapshot, paid);
+        };
+
+        if let Some(liquidator) = liquidator.filter(|_| reward > 0) {
+            if let Err(err) = call_vault_with_transfers(
+                vault,
+                "TransferToReceiver",
+                (token, liquidator, reward, stream_id, 0u128),
+                vft_transfers(token),
+            )
+            .await
+            {
+                restore(0);
+                return Err(err);
+            }
+        }
+
+        if refund > 0 {
+            if let Err(err) = call_vault(
+                vault,
+                "ReleaseFromStream",
+                (sender, token, refund, stream_id),
+            )
+            .await
+            {
+                restore(reward);
+                return Err(err);
+            }
+        }
+
+        Ok((streamed, refund, reward))
+    }
+}
+
+#[service(events = StreamEvent)]
+impl StreamService {
+    // ---- Commands ----
+
+    pub async fn create_stream(
+        &mut self,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+    ) -> Result<u64, StreamError> {
+        let now = exec::block_timestamp() / 1000;
+        self.open_stream(
+            msg::source(),
+            receiver,
+            token,
+            flow_rate,
+            initial_deposit,
+            StreamSchedule::starting_now(now),
+        )
+        .await
+    }
+
+    /// Opens a stream with any combination of the terms in `options`: on
+    /// another account's behalf, to a split group, delayed, with a cliff,
+    /// fixed-duration, curved, and with chosen cancellation rights. For a
+    /// fixed-duration stream `initial_deposit` is the total and `flow_rate`
+    /// is ignored; for a split stream `receiver` is ignored.
+    pub async fn create_stream_with_options(
+        &mut self,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        options: StreamOptions,
+    ) -> Result<u64, StreamError> {
+        let caller = msg::source();
+        let sender = options.sender.unwrap_or(caller);
+        Self::authorize(sender, caller, PermissionScope::CreateStream).await?;
+
+        let receiver = match options.split_group {
+            Some(group_id) => {
+                let splits_router = StreamCoreState::get().config.splits_router;
+                if splits_router == ActorId::zero() {
+                    return Err(StreamError::InvalidConfig);
+                }
+                let group: Option<SplitGroup> =
+                    call_splits(splits_router, "GetSplitGroup", group_id).await?;
+                group.ok_or(StreamError::InvalidReceiver)?.owner
+            }
+            None => receiver,
+        };
+
+        let now = exec::block_timestamp() / 1000;
+        let schedule = options.schedule(now)?;
+        let flow_rate = match schedule.end_time {
+            Some(end_time) => initial_deposit / (end_time - schedule.start_time) as u128,
+            None => flow_rate,
+        };
+        self.open_stream(sender, receiver, token, flow_rate, initial_deposit, schedule)
+            .await
+    }
+
+    /// Opens an open-ended stream that starts accruing at `start_time` and,
+    /// if `cliff_time` is set, keeps everything locked until the cliff.
+    pub async fn create_delayed_stream(
+        &mut self,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        start_time: u64,
+        cliff_time: Option<u64>,
+    ) -> Result<u64, StreamError> {
+        let options = StreamOptions {
+            start_time: Some(start_time),
+            cliff_time,
+            ..StreamOptions::default()
+        };
+        self.create_stream_with_options(receiver, token, flow_rate, initial_deposit, options)
+            .await
+    }
+
+    /// Opens an open-ended stream whose accrual follows `curve` instead of a
+    /// constant per-second rate; `flow_rate` is the curve's base rate.
+    pub async fn create_curved_stream(
+        &mut self,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+        curve: ReleaseCurve,
+    ) -> Result<u64, StreamError> {
+        let options = StreamOptions {
+            curve,
+            ..StreamOptions::default()
+        };
+        self.create_stream_with_options(receiver, token, flow_rate, initial_deposit, options)
+            .await
+    }
+
+    /// Opens a stream that pays exactly `total_amount` between `start_time`
+    /// (now if unset) and `end_time`, then completes on its own. With a
+    /// `cliff_time` this is a vesting schedule. The full amount is allocated
+    /// in the vault up front, so no minimum buffer applies.
+    pub async fn create_fixed_stream(
+        &mut self,
+        receiver: ActorId,
+        token: ActorId,
+        total_amount: u128,
+        start_time: Option<u64>,
+        cliff_time: Option<u64>,
+        end_time: u64,
+    ) -> Result<u64, StreamError> {
+        let options = StreamOptions {
+            start_time,
+            cliff_time,
+            end_time: Some(end_time),
+            ..StreamOptions::default()
+        };
+        self.create_stream_with_options(receiver, token, 0, total_amount, options)
+            .await
+    }
+
+    /// Opens a stream funded from `sender`'s vault balance on their behalf.
+    /// The caller needs the `CreateStream` scope from `sender`.
+    pub async fn create_stream_for(
+        &mut self,
+        sender: ActorId,
+        receiver: ActorId,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+    ) -> Result<u64, StreamError> {
+        let options = StreamOptions {
+            sender: Some(sender),
+            ..StreamOptions::default()
+        };
+        self.create_stream_with_options(receiver, token, flow_rate, initial_deposit, options)
+            .await
+    }
+
+    /// Opens a stream whose withdrawals are distributed across the weighted
+    /// recipients of SplitsRouter group `group_id`. The group's owner becomes
+    /// the receiver, able to withdraw and to cancel if allowed, but the funds
+    /// always go to the group.
+    pub async fn create_split_stream(
+        &mut self,
+        group_id: u64,
+        token: ActorId,
+        flow_rate: u128,
+        initial_deposit: u128,
+    ) -> Result<u64, StreamError> {
+        let options = StreamOptions {
+            split_group: Some(group_id),
+            ..StreamOptions::default()
+        };
+        self.create_stream_with_options(ActorId::zero(), token, flow_rate, initial_deposit, options)
+            .await
+    }
+
+    /// Opens one stream per entry, all starting now, with a single vault
+    /// allocation per token. Either every stream is created or none is.
+    pub async fn create_streams_batch(
+        &mut self,
+        streams: Vec<StreamParams>,
+    ) -> Result<Vec<u64>, StreamError> {
+        StreamCoreState::get().ensure_not_paused()?;
+        if streams.is_empty() || streams.len() > MAX_BATCH_SIZE {
+            return Err(StreamError::InvalidBatch);
+        }
+        let sender = msg::source();
+        let now = exec::block_timestamp() / 1000;
+        for params in &streams {
+            Self::check_stream_params(
+                sender,
+                params.receiver,
+                params.token,
+                params.flow_rate,
+                params.initial_deposit,
+                None,
+                &ReleaseCurve::Linear,
+            )?;
+        }
+
+        // Each new stream sends its keeper check with `keeper_gas` out of this
+        // message once the vault replies; refuse the batch up front rather
+        // than leave the last streams unwatched.
+        let state = StreamCoreState::get();
+        let keeper_gas = state.config.keeper_gas.saturating_mul(streams.len() as u64);
+        if keeper_gas > 0
+            && exec::gas_available()
+                < keeper_gas.saturating_add(VAULT_GAS_LIMIT + KEEPER_GAS_MARGIN)
+        {
+            return Err(StreamError::GasReservationFailed);
+        }
+
+        let first_id = state.config.next_stream_id;
+        state.config.next_stream_id += streams.len() as u64;
+        let allocations: Vec<StreamAllocation> = streams
+            .iter()
+            .zip(first_id..)
+            .map(|(params, stream_id)| StreamAllocation {
+                stream_id,
+                token: params.token,
+                amount: params.initial_deposit,
+            })
+            .collect();
+
+        call_vault(state.config.token_vault, "AllocateToStreams", (sender, allocations)).await?;
+
+        let mut ids = Vec::with_capacity(streams.len());
+        for (params, id) in streams.into_iter().zip(first_id..) {
+            self.record_stream(
+                id,
+                sender,
+                params.receiver,
+                params.token,
+                params.flow_rate,
+                params.initial_deposit,
+                StreamSchedule {
+                    cancel_rights: params.cancel_rights,
+                    ..StreamSchedule::starting_now(now)
+                },
+            );
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    pub async fn update_stream(
+        &mut self,
+        stream_id: u64,
+        new_flow_rate: u128,
+    ) -> Result<(), StreamError> {
+        let caller = msg::source();
+        let sender = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?
+            .sender;
+        Self::authorize(sender, caller, PermissionScope::UpdateStream).await?;
+
+        let state = StreamCoreState::get();
+        state.ensure_not_paused()?;
+        let now = exec::block_timestamp() / 1000;
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed) {
+            return Err(StreamError::InvalidStatus);
+        }
+        if stream.end_time.is_some() {
+            return Err(StreamError::FixedDuration);
+        }
+        if new_flow_rate == 0 {
+            return Err(StreamError::ZeroFlowRate);
+        }
+        // Cutting the rate of a stream the sender may not cancel would be a
+        // cancellation by other means.
+        if !stream.cancel_rights.cancelable_by_sender
+            && new_flow_rate < StreamCoreState::flow_rate_at(stream, now)
+        {
+            return Err(StreamError::NotCancelable);
+        }
+
+        let active = stream.status == StreamStatus::Active;
+        if active {
+            StreamCoreState::remove_flow(&mut state.account_flows, stream);
+        }
+        StreamCoreState::settle(stream, now);
+        let old_flow_rate = stream.flow_rate;
+        stream.flow_rate = new_flow_rate;
+        if active {
+            StreamCoreState::add_flow(&mut state.account_flows, stream);
+        }
+        let streamed = stream.streamed;
+        state.record_checkpoint(stream_id, now);
+        state.schedule_keeper_check(stream_id);
+
+        self.notify_on(StreamEvent::FlowRateUpdated {
+            id: stream_id,
+            old_flow_rate,
+            new_flow_rate,
+            streamed,
+            updated_at: now,
+        })
+        .expect("Failed to emit FlowRateUpdated");
+
+        Ok(())
+    }
+
+    /// Queues a switch to `new_rate` at `at`. The change is applied when the
+    /// stream next settles, with accrual split exactly at `at`. A change
+    /// already queued for the same time is replaced.
+    pub async fn schedule_rate_change(
+        &mut self,
+        stream_id: u64,
+        at: u64,
+        new_rate: u128,
+    ) -> Result<(), StreamError> {
+        let caller = msg::source();
+        let sender = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?
+            .sender;
+        Self::authorize(sender, caller, PermissionScope::UpdateStream).await?;
+        StreamCoreState::get().ensure_not_paused()?;
+
+        let now = exec::block_timestamp() / 1000;
+        let stream = StreamCoreState::get()
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed) {
+            return Err(StreamError::InvalidStatus);
+        }
+        if stream.end_time.is_some() {
+            return Err(StreamError::FixedDuration);
+        }
+        if new_rate == 0 {
+            return Err(StreamError::ZeroFlowRate);
+        }
+        if at <= now {
+            return Err(StreamError::InvalidSchedule);
+        }
+        if !stream.cancel_rights.cancelable_by_sender
+            && new_rate < StreamCoreState::flow_rate_at(stream, at - 1)
+        {
+            return Err(StreamError::NotCancelable);
+        }
+
+        let change = RateChange { at, new_rate };
+        match stream.rate_changes.binary_search_by_key(&at, |change| change.at) {
+            Ok(index) => stream.rate_changes[index] = change,
+            Err(_) if stream.rate_changes.len() >= MAX_RATE_CHANGES => {
+                return Err(StreamError::InvalidSchedule);
+            }
+            Err(index) => stream.rate_changes.insert(index, change),
+        }
+        let state = StreamCoreState::get();
+        state.record_checkpoint(stream_id, now);
+        state.schedule_keeper_check(stream_id);
+
+        self.notify_on(StreamEvent::RateChangeScheduled {
+            id: stream_id,
+            at,
+            new_rate,
+        })
+        .expect("Failed to emit RateChangeScheduled");
+
+        Ok(())
+    }
+
+    /// Drops the rate change queued for `at` if it has not taken effect yet.
+    pub async fn cancel_rate_change(&mut self, stream_id: u64, at: u64) -> Result<(), StreamError> {
+        let caller = msg::source();
+        let sender = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?
+            .sender;
+        Self::authorize(sender, caller, PermissionScope::UpdateStream).await?;
+
+        let now = exec::block_timestamp() / 1000;
+        let stream = StreamCoreState::get()
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        let index = stream
+            .rate_changes
+            .iter()
+            .position(|change| change.at == at && change.at > now)
+            .ok_or(StreamError::InvalidSchedule)?;
+        // Dropping a queued increase lowers the rate just like scheduling a
+        // decrease.
+        if !stream.cancel_rights.cancelable_by_sender
+            && stream.rate_changes[index].new_rate > StreamCoreState::flow_rate_at(stream, at - 1)
+        {
+            return Err(StreamError::NotCancelable);
+        }
+        stream.rate_changes.remove(index);
+        let state = StreamCoreState::get();
+        state.record_checkpoint(stream_id, now);
+        state.schedule_keeper_check(stream_id);
+
+        self.notify_on(StreamEvent::RateChangeCanceled { id: stream_id, at })
+            .expect("Failed to emit RateChangeCanceled");
+
+        Ok(())
+    }
+
+    /// Stops a stream if the caller holds the cancellation right: the sender
+    /// (or an operator with their `StopStream` scope) when it is
+    /// `cancelable_by_sender`, the receiver when it is `cancelable_by_receiver`.
+    /// A receiver cancelling is first paid everything earned so far.
+    pub async fn stop_stream(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let caller = msg::source();
+        let stream = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        let (sender, receiver, rights) = (stream.sender, stream.receiver, stream.cancel_rights);
+        let by_receiver = caller == receiver;
+        if by_receiver {
+            if !rights.cancelable_by_receiver {
+                return Err(StreamError::NotCancelable);
+            }
+        } else {
+            Self::authorize(sender, caller, PermissionScope::StopStream).await?;
+            if !rights.cancelable_by_sender {
+                return Err(StreamError::NotCancelable);
+            }
+        }
+
+        let now = exec::block_timestamp() / 1000;
+        self.complete_if_ended(stream_id, now);
+        let stream = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed) {
+            return Err(StreamError::InvalidStatus);
+        }
+
+        if by_receiver {
+            match self.pay_out(stream_id, caller, None, None).await {
+                Ok(_) | Err(StreamError::NothingToWithdraw) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        // The payout awaited the vault, and the sender may have stopped the
+        // stream in the meantime; the receiver keeps what was paid.
+        let stream = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed) {
+            return Err(StreamError::InvalidStatus);
+        }
+        let withdrawn = stream.withdrawn;
+
+        let (streamed, refunded, _) = Self::stop_and_refund(stream_id, now, None).await?;
+
+        self.notify_on(StreamEvent::Stopped {
+            id: stream_id,
+            streamed,
+            withdrawn,
+            refunded,
+            stopped_at: now,
+        })
+        .expect("Failed to emit Stopped");
+        self.hook_stream_stopped(stream_id);
+
+        Ok(())
+    }
+
+    /// Stops several of the caller's streams, refunding their buffers with a
+    /// single vault release per token. If the vault rejects the release every
+    /// stream is restored and nothing is stopped.
+    pub async fn stop_streams_batch(&mut self, stream_ids: Vec<u64>) -> Result<(), StreamError> {
+        check_batch(&stream_ids)?;
+        let caller = msg::source();
+        let now = exec::block_timestamp() / 1000;
+
+        for &stream_id in &stream_ids {
+            let stream = StreamCoreState::get()
+                .streams
+                .get(&stream_id)
+                .ok_or(StreamError::NotFound)?;
+            if stream.sender != caller {
+                return Err(StreamError::Unauthorized);
+            }
+            if !stream.cancel_rights.cancelable_by_sender {
+                return Err(StreamError::NotCancelable);
+            }
+            self.complete_if_ended(stream_id, now);
+        }
+
+        let state = StreamCoreState::get();
+        for stream_id in &stream_ids {
+            if matches!(
+                state.streams[stream_id].status,
+                StreamStatus::Stopped | StreamStatus::Completed
+            ) {
+                return Err(StreamError::InvalidStatus);
+            }
+        }
+
+        let mut closed = Vec::with_capacity(stream_ids.len());
+        let mut releases = Vec::new();
+        for &stream_id in &stream_ids {
+            let (snapshot, refund) = state
+                .close_stream(stream_id, now)
+                .ok_or(StreamError::NotFound)?;
+            if refund > 0 {
+                releases.push(StreamAllocation {
+                    stream_id,
+                    token: state.streams[&stream_id].token,
+                    amount: refund,
+                });
+            }
+            closed.push((stream_id, snapshot, refund));
+        }
+
+        if !releases.is_empty() {
+            if let Err(err) =
+                call_vault(state.config.token_vault, "ReleaseFromStreams", (caller, releases)).await
+            {
+                let state = StreamCoreState::get();
+                for (stream_id, snapshot, _) in &closed {
+                    state.reopen_stream(*stream_id, snapshot, 0);
+                }
+                return Err(err);
+            }
+        }
+
+        let state = StreamCoreState::get();
+        for (stream_id, _, refunded) in closed {
+            // A stream with nothing left to refund may have been pruned while
+            // the release was in flight; its summary holds the final totals.
+            let (streamed, withdrawn) = match state.streams.get(&stream_id) {
+                Some(stream) => (stream.streamed, stream.withdrawn),
+                None => match state.archived.get(&stream_id) {
+                    Some(archived) => (archived.streamed, archived.streamed),
+                    None => continue,
+                },
+            };
+            self.notify_on(StreamEvent::Stopped {
+                id: stream_id,
+                streamed,
+                withdrawn,
+                refunded,
+                stopped_at: now,
+            })
+            .expect("Failed to emit Stopped");
+            self.hook_stream_stopped(stream_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn pause_stream(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let caller = msg::source();
+        let now = exec::block_timestamp() / 1000;
+
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if stream.sender != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if stream.status != StreamStatus::Active {
+            return Err(StreamError::InvalidStatus);
+        }
+        if stream.end_time.is_some() {
+            return Err(StreamError::FixedDuration);
+        }
+        // An open-ended pause would stop the stream without the right to.
+        if !stream.cancel_rights.cancelable_by_sender {
+            return Err(StreamError::NotCancelable);
+        }
+
+        StreamCoreState::remove_flow(&mut state.account_flows, stream);
+        StreamCoreState::settle(stream, now);
+        stream.status = StreamStatus::Paused;
+        let streamed = stream.streamed;
+        state.active_count = state.active_count.saturating_sub(1);
+        state.record_checkpoint(stream_id, now);
+
+        self.notify_on(StreamEvent::Paused {
+            id: stream_id,
+            streamed,
+            paused_at: now,
+        })
+        .expect("Failed to emit Paused");
+
+        Ok(())
+    }
+
+    pub fn resume_stream(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let caller = msg::source();
+        let now = exec::block_timestamp() / 1000;
+
+        state.ensure_not_paused()?;
+
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if stream.sender != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if stream.status != StreamStatus::Paused {
+            return Err(StreamError::InvalidStatus);
+        }
+
+        // Applies any rate change that fell due while paused.
+        StreamCoreState::settle(stream, now);
+        stream.last_update = now.max(stream.start_time);
+        stream.status = StreamStatus::Active;
+        StreamCoreState::add_flow(&mut state.account_flows, stream);
+        state.active_count += 1;
+        state.record_checkpoint(stream_id, now);
+        state.schedule_keeper_check(stream_id);
+
+        self.notify_on(StreamEvent::Resumed {
+            id: stream_id,
+            resumed_at: now,
+        })
+        .expect("Failed to emit Resumed");
+
+        Ok(())
+    }
+
+    pub async fn deposit(&mut self, stream_id: u64, amount: u128) -> Result<(), StreamError> {
+        let caller = msg::source();
+        let sender = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?
+            .sender;
+        Self::authorize(sender, caller, PermissionScope::DepositOnBehalf).await?;
+
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed) {
+            return Err(StreamError::InvalidStatus);
+        }
+        if stream.end_time.is_some() {
+            return Err(StreamError::FixedDuration);
+        }
+        if amount == 0 {
+            return Err(StreamError::ZeroAmount);
+        }
+
+        // Settle first so a drained stream does not retroactively accrue the
+        // time it spent without buffer once the top-up lands.
+        StreamCoreState::settle_tracked(&mut state.account_flows, stream, now);
+        stream.deposited = stream.deposited.saturating_add(amount);
+        let (token, new_deposited) = (stream.token, stream.deposited);
+
+        if let Err(err) = call_vault(
+            state.config.token_vault,
+            "AllocateToStream",
+            (sender, token, amount, stream_id),
+        )
+        .await
+        {
+            if let Some(stream) = StreamCoreState::get().streams.get_mut(&stream_id) {
+                stream.deposited = stream.deposited.saturating_sub(amount);
+            }
+            return Err(err);
+        }
+        let state = StreamCoreState::get();
+        state.record_checkpoint(stream_id, now);
+        state.schedule_keeper_check(stream_id);
+
+        self.notify_on(StreamEvent::Deposited {
+            id: stream_id,
+            sender,
+            amount,
+            new_deposited,
+        })
+        .expect("Failed to emit Deposited");
+
+        Ok(())
+    }
+
+    pub async fn withdraw(&mut self, stream_id: u64) -> Result<u128, StreamError> {
+        self.pay_out(stream_id, msg::source(), None, None).await
+    }
+
+    /// Withdraws `amount` (everything withdrawable if `None`) and sends it to
+    /// `destination`. Passing the TokenVault's address credits the receiver's
+    /// own vault balance instead, ready to fund new streams.
+    pub async fn withdraw_to(
+        &mut self,
+        stream_id: u64,
+        amount: Option<u128>,
+        destination: ActorId,
+    ) -> Result<u128, StreamError> {
+        if destination == ActorId::zero() {
+            return Err(StreamError::InvalidReceiver);
+        }
+        self.pay_out(stream_id, msg::source(), amount, Some(destination)).await
+    }
+
+    /// Withdraws from several streams the caller receives, paying out with a
+    /// single vault transfer per token. Returns the amount paid from each
+    /// stream, after fees, in the order given; if the transfer is rejected
+    /// nothing is paid.
+    pub async fn withdraw_many(&mut self, stream_ids: Vec<u64>) -> Result<Vec<u128>, StreamError> {
+        check_batch(&stream_ids)?;
+        let caller = msg::source();
+        let now = exec::block_timestamp() / 1000;
+
+        for &stream_id in &stream_ids {
+            let stream = StreamCoreState::get()
+                .streams
+                .get(&stream_id)
+                .ok_or(StreamError::NotFound)?;
+            if stream.receiver != caller {
+                return Err(StreamError::Unauthorized);
+            }
+            if stream.split_group.is_some() {
+                return Err(StreamError::InvalidReceiver);
+            }
+            self.complete_if_ended(stream_id, now);
+        }
+
+        // Book every withdrawal before awaiting, as `withdraw` does, and undo
+        // them all if the vault refuses the transfer.
+        let state = StreamCoreState::get();
+        let mut amounts = Vec::with_capacity(stream_ids.len());
+        let mut fees = Vec::with_capacity(stream_ids.len());
+        let mut transfers = Vec::new();
+        let mut fee_entries = Vec::new();
+        for stream_id in &stream_ids {
+            let stream = state
+                .streams
+                .get_mut(stream_id)
+                .ok_or(StreamError::NotFound)?;
+            StreamCoreState::settle_tracked(&mut state.account_flows, stream, now);
+            let withdrawable = StreamCoreState::withdrawable_balance(stream, now);
+            stream.withdrawn = stream.withdrawn.saturating_add(withdrawable);
+            let token = stream.token;
+            let fee = state.protocol_fee(token, withdrawable);
+            if withdrawable > 0 {
+                transfers.push(StreamAllocation {
+                    stream_id: *stream_id,
+                    token,
+                    amount: withdrawable - fee,
+                });
+            }
+            if fee > 0 {
+                fee_entries.push(StreamAllocation {
+                    stream_id: *stream_id,
+                    token,
+                    amount: fee,
+                });
+            }
+            amounts.push(withdrawable);
+            fees.push(fee);
+        }
+        if transfers.is_empty() {
+            return Err(StreamError::NothingToWithdraw);
+        }
+
+        // The vault pays out once per token.
+        let mut tokens: Vec<ActorId> = transfers.iter().map(|entry| entry.token).collect();
+        tokens.sort();
+        tokens.dedup();
+        let token_transfers = tokens.into_iter().map(vft_transfers).sum();
+        if let Err(err) = call_vault_with_transfers(
+            state.config.token_vault,
+            "TransferToReceiverBatch",
+            (caller, transfers, fee_entries),
+            token_transfers,
+        )
+        .await
+        {
+            let state = StreamCoreState::get();
+            for (stream_id, amount) in stream_ids.iter().zip(&amounts) {
+                if let Some(stream) = state.streams.get_mut(stream_id) {
+                    stream.withdrawn = stream.withdrawn.saturating_sub(*amount);
+                }
+            }
+            return Err(err);
+        }
+
+        let mut paid = Vec::with_capacity(stream_ids.len());
+        for ((&stream_id, &amount), &fee) in stream_ids.iter().zip(&amounts).zip(&fees) {
+            if amount > 0 {
+                self.notify_on(StreamEvent::Withdrawn {
+                    id: stream_id,
+                    receiver: caller,
+                    destination: caller,
+                    amount: amount - fee,
+                    fee,
+                    distribution: Vec::new(),
+                    timestamp: now,
+                })
+                .expect("Failed to emit Withdrawn");
+                self.hook_stream_funds(stream_id, caller, amount - fee);
+            }
+            paid.push(amount - fee);
+        }
+
+        Ok(paid)
+    }
+
+    /// Opts the stream into automatic withdrawals to its receiver every
+    /// `interval_blocks`. The program reserves `gas_reserve` from this
+    /// message's gas and schedules itself with delayed messages; each payout
+    /// funds the next from what is left, until it drops below
+    /// `AUTO_PAYOUT_MIN_GAS`.
+    pub fn enable_auto_payout(
+        &mut self,
+        stream_id: u64,
+        interval_blocks: u32,
+        gas_reserve: u64,
+    ) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let caller = msg::source();
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if stream.sender != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed)
+            || stream.auto_payout.is_some()
+        {
+            return Err(StreamError::InvalidStatus);
+        }
+        if interval_blocks == 0 {
+            return Err(StreamError::InvalidSchedule);
+        }
+        if gas_reserve < AUTO_PAYOUT_MIN_GAS {
+            return Err(StreamError::GasReservationFailed);
+        }
+
+        let pending_message = schedule_payout_tick(stream_id, gas_reserve, interval_blocks)?;
+        let next_payout_block = exec::block_height().saturating_add(interval_blocks);
+        stream.auto_payout = Some(AutoPayout {
+            interval_blocks,
+            next_payout_block,
+            pending_message,
+        });
+
+        self.notify_on(StreamEvent::AutoPayoutEnabled {
+            id: stream_id,
+            interval_blocks,
+            next_payout_block,
+        })
+        .expect("Failed to emit AutoPayoutEnabled");
+
+        Ok(())
+    }
+
+    /// Stops automatic withdrawals; callable by the sender or the receiver.
+    /// The already-sent delayed message still arrives but is ignored.
+    pub fn cancel_auto_payout(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let caller = msg::source();
+        let stream = state
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+
+        if stream.sender != caller && stream.receiver != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if stream.auto_payout.take().is_none() {
+            return Err(StreamError::InvalidStatus);
+        }
+
+        self.notify_on(StreamEvent::AutoPayoutCanceled { id: stream_id })
+            .expect("Failed to emit AutoPayoutCanceled");
+
+        Ok(())
+    }
 
-        let stream = Stream {
-            id,
-            sender,
-            receiver,
-            token,
-            flow_rate,
-            start_time: now,
-            last_update: now,
-            deposited: initial_deposit,
-            withdrawn: 0,
-            streamed: 0,
-            status: StreamStatus::Active,
+    /// Delayed message StreamCore sends to itself for each auto-payout:
+    /// withdraws to the current receiver, then schedules the next payout
+    /// while the stream is running and gas remains.
+    pub async fn process_auto_payout(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        if msg::source() != exec::program_id() {
+            return Err(StreamError::Unauthorized);
+        }
+        let stream = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        let Some(auto_payout) = stream
+            .auto_payout
+            .clone()
+            .filter(|auto_payout| auto_payout.pending_message == gstd_msg::id())
+        else {
+            // Canceled or replaced since this tick was sent.
+            return Ok(());
         };
 
-        state.streams.insert(id, stream);
-        state.sender_streams.entry(sender).or_default().push(id);
-        state.receiver_streams.entry(receiver).or_default().push(id);
-        state.active_count += 1;
+        // A failed payout is retried at the next tick instead of ending the
+        // schedule.
+        let _ = self.pay_out(stream_id, stream.receiver, None, None).await;
 
-        let payload = encode_call(
-            "VaultService",
-            "AllocateToStream",
-            (sender, token, initial_deposit, id)
-        );
-        gstd_msg::send_bytes_with_gas(
-            state.config.token_vault,
-            payload,
-            5_000_000_000,
-            0
-        ).expect("Vault allocate failed");
+        let stream = StreamCoreState::get()
+            .streams
+            .get_mut(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        let running = !matches!(stream.status, StreamStatus::Stopped | StreamStatus::Completed);
+        let gas = exec::gas_available().saturating_sub(AUTO_PAYOUT_GAS_MARGIN);
+        let interval_blocks = auto_payout.interval_blocks;
+        let next = if running && gas >= AUTO_PAYOUT_MIN_GAS {
+            schedule_payout_tick(stream_id, gas, interval_blocks).ok()
+        } else {
+            None
+        };
 
-        id
+        stream.auto_payout = next.map(|pending_message| AutoPayout {
+            next_payout_block: exec::block_height().saturating_add(interval_blocks),
+            pending_message,
+            ..auto_payout
+        });
+        if stream.auto_payout.is_none() {
+            self.notify_on(StreamEvent::AutoPayoutCanceled { id: stream_id })
+                .expect("Failed to emit AutoPayoutCanceled");
+        }
+
+        Ok(())
     }
 
-    pub fn update_stream(&mut self, stream_id: u64, new_flow_rate: u128) {
+    /// Registers (or with `None` removes) the caller's receiver hook. Once
+    /// set, StreamCore sends `OnStreamFunds(stream_id, token, amount)` to the
+    /// hook's service after every payout to the caller and
+    /// `OnStreamStopped(stream_id, token, streamed, withdrawn)` when one of
+    /// its streams is stopped or liquidated.
+    pub fn set_receiver_hook(&mut self, hook: Option<ReceiverHook>) -> Result<(), StreamError> {
         let state = StreamCoreState::get();
         let caller = msg::source();
-        let now = exec::block_timestamp() / 1000;
+        if let Some(ReceiverHook { service, gas_limit }) = &hook {
+            if service.is_empty()
+                || service.len() > MAX_HOOK_SERVICE_LEN
+                || *gas_limit == 0
+                || *gas_limit > MAX_RECEIVER_HOOK_GAS
+            {
+                return Err(StreamError::InvalidHook);
+            }
+        }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+        match hook.clone() {
+            Some(hook) => state.receiver_hooks.insert(caller, hook),
+            None => state.receiver_hooks.remove(&caller),
+        };
 
-        assert!(stream.sender == caller, "Only sender can update stream");
-        assert!(
-            stream.status != StreamStatus::Stopped,
-            "Cannot update a stopped stream"
-        );
-        assert!(new_flow_rate > 0, "Flow rate must be > 0");
+        self.notify_on(StreamEvent::ReceiverHookSet {
+            receiver: caller,
+            hook,
+        })
+        .expect("Failed to emit ReceiverHookSet");
 
-        StreamCoreState::settle(stream, now);
-        stream.flow_rate = new_flow_rate;
+        Ok(())
     }
 
-    pub fn stop_stream(&mut self, stream_id: u64) {
+    /// Lets `operator` (e.g. a marketplace) move this stream's receiver rights
+    /// via `transfer_stream_receiver_from`; `None` clears the approval.
+    pub fn approve_receiver_transfer(
+        &mut self,
+        stream_id: u64,
+        operator: Option<ActorId>,
+    ) -> Result<(), StreamError> {
         let state = StreamCoreState::get();
         let caller = msg::source();
-        let now = exec::block_timestamp() / 1000;
+        let stream = state.streams.get(&stream_id).ok_or(StreamError::NotFound)?;
+        if stream.receiver != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        if stream.split_group.is_some() {
+            return Err(StreamError::InvalidReceiver);
+        }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+        match operator {
+            Some(operator) => state.receiver_approvals.insert(stream_id, operator),
+            None => state.receiver_approvals.remove(&stream_id),
+        };
 
-        assert!(stream.sender == caller, "Only sender can stop stream");
-        assert!(
-            stream.status != StreamStatus::Stopped,
-            "Stream already stopped"
-        );
+        self.notify_on(StreamEvent::ReceiverApproved {
+            id: stream_id,
+            receiver: caller,
+            operator,
+        })
+        .expect("Failed to emit ReceiverApproved");
 
-        StreamCoreState::settle(stream, now);
-        stream.status = StreamStatus::Stopped;
-        stream.flow_rate = 0;
-        state.active_count = state.active_count.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Settles earned funds to the caller and makes `new_receiver` the
+    /// receiver of all future accrual. Returns the amount paid out after the
+    /// protocol fee.
+    pub async fn transfer_stream_receiver(
+        &mut self,
+        stream_id: u64,
+        new_receiver: ActorId,
+    ) -> Result<u128, StreamError> {
+        let caller = msg::source();
+        let stream = StreamCoreState::get()
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        if stream.receiver != caller {
+            return Err(StreamError::Unauthorized);
+        }
+        let now = exec::block_timestamp() / 1000;
+        self.reassign_receiver(stream_id, caller, new_receiver, now).await
     }
 
-    pub fn pause_stream(&mut self, stream_id: u64) {
+    /// Same as `transfer_stream_receiver`, callable by the current receiver or
+    /// their approved operator. `from` must still be the receiver, so a stale
+    /// marketplace listing cannot move rights that changed hands meanwhile.
+    pub async fn transfer_stream_receiver_from(
+        &mut self,
+        stream_id: u64,
+        from: ActorId,
+        new_receiver: ActorId,
+    ) -> Result<u128, StreamError> {
         let state = StreamCoreState::get();
         let caller = msg::source();
+        let stream = state.streams.get(&stream_id).ok_or(StreamError::NotFound)?;
+        if stream.receiver != from {
+            return Err(StreamError::InvalidReceiver);
+        }
+        let approved = state.receiver_approvals.get(&stream_id) == Some(&caller);
+        if caller != from && !approved {
+            return Err(StreamError::Unauthorized);
+        }
         let now = exec::block_timestamp() / 1000;
+        self.reassign_receiver(stream_id, from, new_receiver, now).await
+    }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+    pub async fn liquidate(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        let stream = state
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        let min_buffer_seconds = state.min_buffer_seconds(stream.token);
 
-        assert!(stream.sender == caller, "Only sender can pause stream");
-        assert!(stream.status == StreamStatus::Active, "Stream is not active");
+        if !StreamCoreState::should_liquidate(stream, now, min_buffer_seconds) {
+            return Err(StreamError::NotLiquidatable);
+        }
 
-        StreamCoreState::settle(stream, now);
-        stream.status = StreamStatus::Paused;
-        state.active_count = state.active_count.saturating_sub(1);
+        let liquidator = msg::source();
+        let (streamed, refunded, reward) =
+            Self::stop_and_refund(stream_id, now, Some(liquidator)).await?;
+
+        self.notify_on(StreamEvent::Liquidated {
+            id: stream_id,
+            liquidator,
+            streamed,
+            refunded,
+            reward,
+            liquidated_at: now,
+        })
+        .expect("Failed to emit Liquidated");
+        self.hook_stream_stopped(stream_id);
+
+        Ok(())
     }
 
-    pub fn resume_stream(&mut self, stream_id: u64) {
+    /// Delayed message StreamCore sends to itself when a stream's buffer is
+    /// expected to run low: liquidates it without a reward if it is now below
+    /// the minimum, otherwise schedules the next check.
+    pub async fn process_keeper_check(&mut self, stream_id: u64) -> Result<(), StreamError> {
+        let program_id = exec::program_id();
+        if msg::source() != program_id {
+            return Err(StreamError::Unauthorized);
+        }
         let state = StreamCoreState::get();
-        let caller = msg::source();
+        if state.keeper_checks.get(&stream_id) != Some(&gstd_msg::id()) {
+            // Superseded by a newer check after a top-up or rate change.
+            return Ok(());
+        }
+        state.keeper_checks.remove(&stream_id);
+
         let now = exec::block_timestamp() / 1000;
+        let stream = state
+            .streams
+            .get(&stream_id)
+            .ok_or(StreamError::NotFound)?;
+        let min_buffer_seconds = state.min_buffer_seconds(stream.token);
+        if !StreamCoreState::should_liquidate(stream, now, min_buffer_seconds) {
+            // Checks can arrive early (stepped curves, slower blocks, rate
+            // changes). This message only carries `keeper_gas`, so the next
+            // check is funded from what is left of it.
+            let gas = exec::gas_available().saturating_sub(KEEPER_GAS_MARGIN);
+            state.send_keeper_check(stream_id, gas);
+            return Ok(());
+        }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+        let (streamed, refunded, reward) = Self::stop_and_refund(stream_id, now, None).await?;
 
-        assert!(stream.sender == caller, "Only sender can resume stream");
-        assert!(
-            stream.status == StreamStatus::Paused,
-            "Stream is not paused"
-        );
+        self.notify_on(StreamEvent::Liquidated {
+            id: stream_id,
+            liquidator: program_id,
+            streamed,
+            refunded,
+            reward,
+            liquidated_at: now,
+        })
+        .expect("Failed to emit Liquidated");
+        self.hook_stream_stopped(stream_id);
 
-        stream.last_update = now;
-        stream.status = StreamStatus::Active;
-        state.active_count += 1;
+        Ok(())
     }
 
-    pub fn deposit(&mut self, stream_id: u64, amount: u128) {
-        let state = StreamCoreState::get();
-        let caller = msg::source();
+    /// Archives finished streams: each must be stopped or completed with
+    /// nothing left to withdraw. Anyone may call this since archiving
+    /// changes no balance; either every stream is archived or none is.
+    pub async fn prune(&mut self, stream_ids: Vec<u64>) -> Result<(), StreamError> {
+        check_batch(&stream_ids)?;
+        let now = exec::block_timestamp() / 1000;
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+        for &stream_id in &stream_ids {
+            self.complete_if_ended(stream_id, now);
+            let stream = StreamCoreState::get()
+                .streams
+                .get(&stream_id)
+                .ok_or(StreamError::NotFound)?;
+            if !StreamCoreState::is_archivable(stream, now) {
+                return Err(StreamError::NotArchivable);
+            }
+        }
 
-        assert!(stream.sender == caller, "Only sender can deposit");
-        assert!(
-            stream.status != StreamStatus::Stopped,
-            "Cannot deposit to a stopped stream"
-        );
-        assert!(amount > 0, "Deposit amount must be > 0");
+        // A payout or refund still awaiting the vault keeps funds allocated
+        // and may yet be rolled back onto the stream, so wait until the
+        // vault has released everything.
+        let vault = StreamCoreState::get().config.token_vault;
+        let allocations = vault_stream_allocations(vault, &stream_ids).await?;
+        if allocations.len() != stream_ids.len() {
+            return Err(StreamError::VaultCallFailed);
+        }
+        if allocations.iter().any(|&allocation| allocation > 0) {
+            return Err(StreamError::NotArchivable);
+        }
 
-        stream.deposited = stream.deposited.saturating_add(amount);
+        // Streams archived by a concurrent `prune` meanwhile are skipped.
+        for stream_id in stream_ids {
+            let Some(archived) = StreamCoreState::get().archive_stream(stream_id) else {
+                continue;
+            };
+            let streamed = archived.streamed;
+            self.notify_on(StreamEvent::StreamArchived {
+                id: stream_id,
+                streamed,
+                archived_at: now,
+            })
+            .expect("Failed to emit StreamArchived");
+        }
+
+        Ok(())
     }
 
-    pub fn withdraw(&mut self, stream_id: u64) -> u128 {
+    pub fn set_liquidation_reward_bps(&mut self, reward_bps: u16) -> Result<(), StreamError> {
         let state = StreamCoreState::get();
-        let caller = msg::source();
-        let now = exec::block_timestamp() / 1000;
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        if reward_bps > MAX_LIQUIDATION_REWARD_BPS {
+            return Err(StreamError::InvalidConfig);
+        }
+        state.config.liquidation_reward_bps = reward_bps;
+        Ok(())
+    }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+    pub fn set_permission_manager(&mut self, permission_manager: ActorId) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.permission_manager = permission_manager;
+        Ok(())
+    }
 
-        assert!(stream.receiver == caller, "Only receiver can withdraw");
+    pub fn set_splits_router(&mut self, splits_router: ActorId) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.splits_router = splits_router;
+        Ok(())
+    }
 
-        StreamCoreState::settle(stream, now);
+    /// Sets the protocol fee charged on withdrawals of `token`; fees are kept
+    /// in the TokenVault's fee treasury.
+    pub fn set_protocol_fee_bps(&mut self, token: ActorId, fee_bps: u16) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        if fee_bps > MAX_PROTOCOL_FEE_BPS {
+            return Err(StreamError::InvalidConfig);
+        }
+        state.token_fee_bps.insert(token, fee_bps);
+        Ok(())
+    }
 
-        let withdrawable = stream
-            .streamed
-            .min(stream.deposited)
-            .saturating_sub(stream.withdrawn);
-        assert!(withdrawable > 0, "Nothing to withdraw");
-
-        let payload = encode_call(
-            "VaultService",
-            "TransferToReceiver",
-            (stream.token, stream.receiver, withdrawable, stream_id)
-        );
-        gstd_msg::send_bytes_with_gas(
-            state.config.token_vault,
-            payload,
-            5_000_000_000,
-            0
-        ).expect("Vault transfer failed");
+    /// Sets the gas attached to each in-program liquidation check; 0 turns the
+    /// keeper off. Streams pick up the change at their next create, rate
+    /// change, top-up or resume.
+    pub fn set_keeper_gas(&mut self, keeper_gas: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.keeper_gas = keeper_gas;
+        Ok(())
+    }
 
-        stream.withdrawn = stream.withdrawn.saturating_add(withdrawable);
+    /// Sets the default minimum buffer, in seconds of flow, required to open
+    /// an open-ended stream and below which it can be liquidated.
+    pub fn set_min_buffer_seconds(&mut self, min_buffer_seconds: u64) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        if min_buffer_seconds == 0 {
+            return Err(StreamError::InvalidConfig);
+        }
+        state.config.min_buffer_seconds = min_buffer_seconds;
+        Ok(())
+    }
 
-        withdrawable
+    /// Overrides the minimum buffer for one token; `None` falls back to the
+    /// default.
+    pub fn set_token_min_buffer_seconds(
+        &mut self,
+        token: ActorId,
+        min_buffer_seconds: Option<u64>,
+    ) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        match min_buffer_seconds {
+            Some(0) => return Err(StreamError::InvalidConfig),
+            Some(seconds) => state.token_min_buffers.insert(token, seconds),
+            None => state.token_min_buffers.remove(&token),
+        };
+        Ok(())
     }
 
-    pub fn liquidate(&mut self, stream_id: u64) {
+    /// First step of the admin handover; `None` withdraws a pending proposal.
+    pub fn propose_admin(&mut self, new_admin: Option<ActorId>) -> Result<(), StreamError> {
         let state = StreamCoreState::get();
-        let now = exec::block_timestamp() / 1000;
-        let min_buffer_seconds = state.config.min_buffer_seconds;
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.pending_admin = new_admin;
+        Ok(())
+    }
 
-        let stream = state.streams.get_mut(&stream_id).expect("Stream not found");
+    /// Second step of the admin handover, called by the proposed admin.
+    pub fn accept_admin(&mut self) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        let caller = msg::source();
+        if state.config.pending_admin != Some(caller) {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.admin = caller;
+        state.config.pending_admin = None;
+        Ok(())
+    }
 
-        assert!(
-            StreamCoreState::should_liquidate(stream, now, min_buffer_seconds),
-            "Stream is not eligible for liquidation"
-        );
+    pub fn emergency_pause(&mut self) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.paused = true;
+        Ok(())
+    }
 
-        StreamCoreState::settle(stream, now);
-        stream.status = StreamStatus::Paused;
-        state.active_count = state.active_count.saturating_sub(1);
+    pub fn emergency_unpause(&mut self) -> Result<(), StreamError> {
+        let state = StreamCoreState::get();
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
+        state.config.paused = false;
+        Ok(())
     }
 
     // ---- Queries ----
 
     pub fn get_stream(&self, stream_id: u64) -> Option<Stream> {
         let state = StreamCoreState::get();
-        state.streams.get(&stream_id).cloned()
+        let now = exec::block_timestamp() / 1000;
+        state
+            .streams
+            .get(&stream_id)
+            .map(|stream| StreamCoreState::view(stream, now))
+    }
+
+    /// Summary of a stream removed by `prune`; `get_stream` no longer finds it.
+    pub fn get_archived_stream(&self, stream_id: u64) -> Option<ArchivedStream> {
+        let state = StreamCoreState::get();
+        state.archived.get(&stream_id).cloned()
     }
 
     pub fn get_withdrawable_balance(&self, stream_id: u64) -> u128 {
@@ -393,6 +3223,64 @@ impl StreamService {
             .unwrap_or(0)
     }
 
+    /// Reward a liquidator would receive for liquidating the stream right now,
+    /// or 0 if the stream is not eligible for liquidation.
+    pub fn get_liquidation_reward(&self, stream_id: u64) -> u128 {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        state
+            .streams
+            .get(&stream_id)
+            .filter(|s| StreamCoreState::should_liquidate(s, now, state.min_buffer_seconds(s.token)))
+            .map(|s| {
+                let buffer = StreamCoreState::closing_buffer(s, now);
+                StreamCoreState::liquidation_reward(buffer, state.config.liquidation_reward_bps)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Rate changes queued for the stream that have not taken effect yet.
+    pub fn get_rate_changes(&self, stream_id: u64) -> Vec<RateChange> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        state
+            .streams
+            .get(&stream_id)
+            .map(|stream| {
+                stream
+                    .rate_changes
+                    .iter()
+                    .filter(|change| change.at > now)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Exact amount the stream had streamed by `timestamp` (unix seconds),
+    /// e.g. for invoicing; later timestamps report the amount streamed so
+    /// far.
+    pub fn streamed_at(&self, stream_id: u64, timestamp: u64) -> Option<u128> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        state.streamed_at(stream_id, timestamp.min(now))
+    }
+
+    pub fn get_checkpoints(&self, stream_id: u64) -> Vec<Checkpoint> {
+        let state = StreamCoreState::get();
+        state.checkpoints.get(&stream_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_receiver_approval(&self, stream_id: u64) -> Option<ActorId> {
+        let state = StreamCoreState::get();
+        state.receiver_approvals.get(&stream_id).copied()
+    }
+
+    pub fn get_receiver_hook(&self, receiver: ActorId) -> Option<ReceiverHook> {
+        let state = StreamCoreState::get();
+        state.receiver_hooks.get(&receiver).cloned()
+    }
+
     pub fn get_sender_streams(&self, sender: ActorId) -> Vec<u64> {
         let state = StreamCoreState::get();
         state
@@ -411,14 +3299,98 @@ impl StreamService {
             .unwrap_or_default()
     }
 
+    /// Full streams sent by `sender`, filtered and paginated; `limit` is
+    /// capped at `MAX_PAGE_SIZE`.
+    pub fn get_sender_streams_page(
+        &self,
+        sender: ActorId,
+        filter: StreamFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<Stream> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        let ids = state.sender_streams.get(&sender).into_iter().flatten().copied();
+        state.page(ids, &filter, offset, limit, now)
+    }
+
+    /// Full streams received by `receiver`, filtered and paginated; `limit`
+    /// is capped at `MAX_PAGE_SIZE`.
+    pub fn get_receiver_streams_page(
+        &self,
+        receiver: ActorId,
+        filter: StreamFilter,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<Stream> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        let ids = state.receiver_streams.get(&receiver).into_iter().flatten().copied();
+        state.page(ids, &filter, offset, limit, now)
+    }
+
+    /// Every stream in the program, filtered and paginated by id.
+    pub fn list_streams(&self, filter: StreamFilter, offset: u32, limit: u32) -> Vec<Stream> {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        state.page(state.streams.keys().copied(), &filter, offset, limit, now)
+    }
+
+    /// Combined flow of `account` in `token`: rates of its active streams
+    /// and everything it can withdraw right now.
+    pub fn get_account_flow(&self, account: ActorId, token: ActorId) -> AccountFlow {
+        let state = StreamCoreState::get();
+        let now = exec::block_timestamp() / 1000;
+        let rates = state.account_rates(account, token, now);
+        let total_withdrawable = state
+            .receiver_streams
+            .get(&account)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| state.streams.get(id))
+            .filter(|stream| stream.token == token)
+            .fold(0u128, |total, stream| {
+                total.saturating_add(StreamCoreState::withdrawable_balance(stream, now))
+            });
+        let signed = |rate: u128| i128::try_from(rate).unwrap_or(i128::MAX);
+
+        AccountFlow {
+            outgoing_rate: rates.outgoing_rate,
+            incoming_rate: rates.incoming_rate,
+            net_flow_rate: signed(rates.incoming_rate).saturating_sub(signed(rates.outgoing_rate)),
+            total_withdrawable,
+        }
+    }
+
     pub fn total_streams(&self) -> u64 {
         let state = StreamCoreState::get();
-        state.streams.len() as u64
+        (state.streams.len() + state.archived.len()) as u64
     }
 
+    /// Active streams, leaving out fixed-duration streams past their end
+    /// that no command has marked completed yet.
     pub fn active_streams(&self) -> u64 {
         let state = StreamCoreState::get();
-        state.active_count
+        let now = exec::block_timestamp() / 1000;
+        let ended = state
+            .streams
+            .values()
+            .filter(|stream| {
+                stream.status == StreamStatus::Active && StreamCoreState::has_ended(stream, now)
+            })
+            .count();
+        state.active_count.saturating_sub(ended as u64)
+    }
+
+    pub fn get_protocol_fee_bps(&self, token: ActorId) -> u16 {
+        let state = StreamCoreState::get();
+        state.token_fee_bps.get(&token).copied().unwrap_or(0)
+    }
+
+    /// Minimum buffer in seconds that applies to streams of `token`.
+    pub fn get_min_buffer_seconds(&self, token: ActorId) -> u64 {
+        let state = StreamCoreState::get();
+        state.min_buffer_seconds(token)
     }
 
     pub fn get_config(&self) -> Config {
@@ -426,9 +3398,344 @@ impl StreamService {
         state.config.clone()
     }
 
-    pub fn set_token_vault(&mut self, vault: ActorId) {
+    pub fn set_token_vault(&mut self, vault: ActorId) -> Result<(), StreamError> {
         let state = StreamCoreState::get();
-        assert!(msg::source() == state.config.admin, "Only admin can set token_vault");
+        if msg::source() != state.config.admin {
+            return Err(StreamError::Unauthorized);
+        }
         state.config.token_vault = vault;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(flow_rate: u128, deposited: u128, start_time: u64, end_time: Option<u64>) -> Stream {
+        Stream {
+            id: 1,
+            sender: ActorId::from(1),
+            receiver: ActorId::from(2),
+            split_group: None,
+            token: ActorId::zero(),
+            flow_rate,
+            start_time,
+            cliff_time: None,
+            end_time,
+            curve: ReleaseCurve::Linear,
+            cancel_rights: CancelRights::SENDER_ONLY,
+            rate_changes: Vec::new(),
+            auto_payout: None,
+            last_update: start_time,
+            deposited,
+            withdrawn: 0,
+            streamed: 0,
+            status: StreamStatus::Active,
+        }
+    }
+
+    #[test]
+    fn pro_rata_spreads_total_evenly() {
+        assert_eq!(released_pro_rata(1_999, 0, 1_000, 0), 0);
+        assert_eq!(released_pro_rata(1_999, 0, 1_000, 500), 999);
+        assert_eq!(released_pro_rata(1_999, 0, 1_000, 999), 1_997);
+        assert_eq!(released_pro_rata(1_999, 0, 1_000, 1_000), 1_999);
+        assert_eq!(released_pro_rata(1_999, 0, 1_000, 5_000), 1_999);
+    }
+
+    #[test]
+    fn pro_rata_handles_totals_below_duration_and_huge_totals() {
+        let year = 365 * 24 * 3_600;
+        assert_eq!(released_pro_rata(500, 100, 100 + year, 100 + year / 2), 250);
+        assert_eq!(released_pro_rata(u128::MAX, 0, 3, 1), u128::MAX / 3);
+        assert_eq!(released_pro_rata(u128::MAX, 0, 3, 3), u128::MAX);
+    }
+
+    #[test]
+    fn fixed_stream_accrues_pro_rata_across_settles() {
+        let mut fixed = stream(1, 1_999, 0, Some(1_000));
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 500), 999);
+
+        StreamCoreState::settle(&mut fixed, 500);
+        assert_eq!(fixed.streamed, 999);
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 750), 1_499);
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 999), 1_997);
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 1_000), 1_999);
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 2_000), 1_999);
+    }
+
+    fn piecewise() -> ReleaseCurve {
+        ReleaseCurve::PiecewiseLinear {
+            segments: vec![
+                CurveSegment { duration: 100, rate_bps: 5_000 },
+                CurveSegment { duration: 100, rate_bps: 20_000 },
+            ],
+        }
+    }
+
+    #[test]
+    fn linear_curve_accrues_per_second() {
+        let curve = ReleaseCurve::Linear;
+        assert_eq!(curve.accrued(7, 1_000, 1_000, 1_010), 70);
+        assert_eq!(curve.accrued(7, 1_000, 1_010, 1_010), 0);
+        assert_eq!(curve.rate_at(7, 1_000, 5_000), 7);
+        assert_eq!(curve.peak_rate(7), 7);
+    }
+
+    #[test]
+    fn stepped_curve_unlocks_whole_periods() {
+        let curve = ReleaseCurve::Stepped { period: 30 };
+        assert_eq!(curve.accrued(2, 0, 0, 29), 0);
+        assert_eq!(curve.accrued(2, 0, 0, 30), 60);
+        assert_eq!(curve.accrued(2, 0, 29, 31), 60);
+        assert_eq!(curve.accrued(2, 0, 31, 59), 0);
+        assert_eq!(curve.accrued(2, 10, 10, 70), 120);
+        assert_eq!(curve.rate_at(2, 0, 45), 2);
+    }
+
+    #[test]
+    fn piecewise_curve_scales_each_segment() {
+        let curve = piecewise();
+        // 100 s at half rate, 100 s at double rate, then the plain rate.
+        assert_eq!(curve.accrued(10, 0, 0, 100), 500);
+        assert_eq!(curve.accrued(10, 0, 50, 150), 250 + 1_000);
+        assert_eq!(curve.accrued(10, 0, 0, 300), 500 + 2_000 + 1_000);
+        assert_eq!(curve.rate_at(10, 0, 99), 5);
+        assert_eq!(curve.rate_at(10, 0, 100), 20);
+        assert_eq!(curve.rate_at(10, 0, 200), 10);
+        assert_eq!(curve.peak_rate(10), 20);
+    }
+
+    #[test]
+    fn minimum_buffer_covers_the_fastest_segment() {
+        let mut curved = stream(10, 0, 0, None);
+        curved.curve = ReleaseCurve::PiecewiseLinear {
+            segments: vec![CurveSegment { duration: 100, rate_bps: 30_000 }],
+        };
+        // A deposit sized for the base rate is below the minimum right away.
+        curved.deposited = 10 * 100;
+        assert!(StreamCoreState::should_liquidate(&curved, 0, 100));
+        curved.deposited = curved.curve.min_deposit(10, 100);
+        assert!(!StreamCoreState::should_liquidate(&curved, 0, 100));
+    }
+
+    #[test]
+    fn minimum_buffer_covers_the_next_tranche() {
+        // A monthly stepped stream with a one-hour minimum buffer.
+        let (month, hour) = (30 * 24 * 3_600, 3_600);
+        let mut stepped = stream(10, 0, 0, None);
+        stepped.curve = ReleaseCurve::Stepped { period: month };
+        assert_eq!(stepped.curve.min_deposit(10, hour), 10 * month as u128);
+        assert_eq!(stepped.curve.min_deposit(10, 2 * month), 20 * month as u128);
+
+        // One hour of buffer cannot pay the first tranche.
+        stepped.deposited = 10 * hour as u128;
+        assert!(StreamCoreState::should_liquidate(&stepped, 0, hour));
+        stepped.deposited = stepped.curve.min_deposit(10, hour);
+        assert!(!StreamCoreState::should_liquidate(&stepped, month - 1, hour));
+        // Once the tranche lands, the next one is no longer covered.
+        assert!(StreamCoreState::should_liquidate(&stepped, month, hour));
+    }
+
+    #[test]
+    fn archived_stream_keeps_its_history() {
+        let mut state = StreamCoreState::new(ActorId::from(9), 60);
+        let mut open = stream(10, 10_000, 0, None);
+        open.withdrawn = 5_000;
+        state.streams.insert(1, open);
+        state.record_checkpoint(1, 0);
+        state.close_stream(1, 500).expect("stream exists");
+
+        assert!(state.archive_stream(1).is_some());
+        assert!(!state.streams.contains_key(&1));
+        assert_eq!(state.streamed_at(1, 0), Some(0));
+        assert_eq!(state.streamed_at(1, 250), Some(2_500));
+        assert_eq!(state.streamed_at(1, 500), Some(5_000));
+        assert_eq!(state.streamed_at(1, 9_000), Some(5_000));
+    }
+
+    #[test]
+    fn stream_options_build_the_schedule() {
+        let plain = StreamOptions::default().schedule(100).expect("valid");
+        assert_eq!((plain.start_time, plain.end_time), (100, None));
+        assert_eq!(plain.cancel_rights, CancelRights::SENDER_ONLY);
+
+        let locked = CancelRights {
+            cancelable_by_sender: false,
+            cancelable_by_receiver: false,
+        };
+        let vesting = StreamOptions {
+            start_time: Some(200),
+            cliff_time: Some(300),
+            end_time: Some(1_000),
+            cancel_rights: locked,
+            ..StreamOptions::default()
+        };
+        let schedule = vesting.schedule(100).expect("valid");
+        assert_eq!((schedule.start_time, schedule.cliff_time), (200, Some(300)));
+        assert_eq!(schedule.cancel_rights, locked);
+
+        let curved_fixed = StreamOptions {
+            curve: ReleaseCurve::Stepped { period: 10 },
+            ..vesting.clone()
+        };
+        assert_eq!(curved_fixed.schedule(100).err(), Some(StreamError::InvalidCurve));
+        let started_earlier = StreamOptions {
+            start_time: Some(50),
+            ..vesting
+        };
+        assert_eq!(started_earlier.schedule(100).err(), Some(StreamError::InvalidSchedule));
+    }
+
+    #[test]
+    fn liquidation_reward_matches_the_closing_buffer() {
+        let mut state = StreamCoreState::new(ActorId::from(9), 60);
+        let mut vesting = stream(10, 10_000, 0, None);
+        vesting.cliff_time = Some(2_000);
+        state.streams.insert(1, vesting);
+
+        // Before the cliff the unvested 5_000 is taken back with the buffer.
+        let quoted = StreamCoreState::closing_buffer(&state.streams[&1], 500);
+        assert_eq!(quoted, 10_000);
+        let (_, buffer) = state.close_stream(1, 500).expect("stream exists");
+        assert_eq!(buffer, quoted);
+        assert_eq!(StreamCoreState::liquidation_reward(buffer, 500), 500);
+    }
+
+    #[test]
+    fn rollback_leaves_pruned_stream_archived() {
+        let mut state = StreamCoreState::new(ActorId::from(9), 60);
+        // Drained and fully withdrawn, so nothing is refunded on stop.
+        let mut drained = stream(10, 10_000, 0, None);
+        drained.withdrawn = 10_000;
+        state.streams.insert(1, drained);
+        state.active_count = 1;
+        state.record_checkpoint(1, 0);
+        let (snapshot, refund) = state.close_stream(1, 1_000).expect("stream exists");
+        assert_eq!(refund, 0);
+        assert!(StreamCoreState::is_archivable(&state.streams[&1], 1_000));
+        state.archive_stream(1).expect("stream exists");
+
+        state.reopen_stream(1, &snapshot, 0);
+        assert_eq!(state.active_count, 0);
+        assert!(!state.streams.contains_key(&1));
+        assert_eq!(state.checkpoints[&1].len(), 2);
+        assert_eq!(state.streamed_at(1, 2_000), Some(10_000));
+    }
+
+    #[test]
+    fn account_rates_apply_due_rate_changes() {
+        let mut state = StreamCoreState::new(ActorId::from(9), 60);
+        let mut open = stream(10, 10_000, 0, None);
+        open.rate_changes.push(RateChange { at: 300, new_rate: 25 });
+        StreamCoreState::add_flow(&mut state.account_flows, &open);
+        state.sender_streams.insert(open.sender, vec![1]);
+        state.receiver_streams.insert(open.receiver, vec![1]);
+        let (sender, receiver, token) = (open.sender, open.receiver, open.token);
+        state.streams.insert(1, open);
+
+        assert_eq!(state.account_rates(sender, token, 299).outgoing_rate, 10);
+        assert_eq!(state.account_rates(sender, token, 300).outgoing_rate, 25);
+        assert_eq!(state.account_rates(receiver, token, 300).incoming_rate, 25);
+        assert_eq!(state.account_rates(receiver, token, 300).outgoing_rate, 0);
+    }
+
+    #[test]
+    fn account_rates_count_what_each_stream_pays_now() {
+        let mut state = StreamCoreState::new(ActorId::from(9), 60);
+        let delayed = stream(10, 10_000, 100, None);
+        let fixed = Stream { id: 2, ..stream(3, 3_000, 0, Some(1_000)) };
+        let curved = Stream {
+            id: 3,
+            curve: piecewise(),
+            ..stream(10, 10_000, 0, None)
+        };
+        let (sender, token) = (delayed.sender, delayed.token);
+        for open in [delayed, fixed, curved] {
+            StreamCoreState::add_flow(&mut state.account_flows, &open);
+            state.sender_streams.entry(sender).or_default().push(open.id);
+            state.streams.insert(open.id, open);
+        }
+
+        // Not started, fixed at 3/s, first curve segment at half rate.
+        assert_eq!(state.account_rates(sender, token, 50).outgoing_rate, 3 + 5);
+        // Started, fixed, second curve segment at double rate.
+        assert_eq!(state.account_rates(sender, token, 150).outgoing_rate, 10 + 3 + 20);
+        // The fixed stream has ended though nothing has completed it yet.
+        assert_eq!(state.account_rates(sender, token, 1_000).outgoing_rate, 10 + 10);
+    }
+
+    #[test]
+    fn settle_splits_accrual_at_queued_rate_changes() {
+        let mut open = stream(10, 1_000_000, 0, None);
+        open.rate_changes = vec![
+            RateChange { at: 100, new_rate: 20 },
+            RateChange { at: 300, new_rate: 5 },
+        ];
+
+        // One change due: 100 s at 10, then 50 s at 20.
+        let mut one = open.clone();
+        StreamCoreState::settle(&mut one, 150);
+        assert_eq!(one.streamed, 1_000 + 1_000);
+        assert_eq!(one.flow_rate, 20);
+        assert_eq!(one.rate_changes, vec![RateChange { at: 300, new_rate: 5 }]);
+
+        // Both due: 100 s at 10, 200 s at 20, then 100 s at 5.
+        StreamCoreState::settle(&mut open, 400);
+        assert_eq!(open.streamed, 1_000 + 4_000 + 500);
+        assert_eq!(open.flow_rate, 5);
+        assert!(open.rate_changes.is_empty());
+
+        // Settling in between does not change the total.
+        StreamCoreState::settle(&mut one, 400);
+        assert_eq!(one.streamed, open.streamed);
+        assert_eq!(one.flow_rate, 5);
+    }
+
+    #[test]
+    fn queued_rate_changes_apply_while_paused_and_on_curves() {
+        let mut paused = stream(10, 1_000_000, 0, None);
+        paused.status = StreamStatus::Paused;
+        paused.rate_changes.push(RateChange { at: 100, new_rate: 30 });
+        StreamCoreState::settle(&mut paused, 200);
+        assert_eq!(paused.streamed, 0);
+        assert_eq!(paused.flow_rate, 30);
+        assert!(paused.rate_changes.is_empty());
+
+        let mut curved = Stream {
+            curve: piecewise(),
+            ..stream(10, 1_000_000, 0, None)
+        };
+        curved.rate_changes.push(RateChange { at: 150, new_rate: 20 });
+        StreamCoreState::settle(&mut curved, 250);
+        // Half rate to 100, double rate to 200 (switching to 20 at 150),
+        // then the plain rate.
+        assert_eq!(curved.streamed, 500 + 1_000 + 2_000 + 1_000);
+        assert_eq!(curved.flow_rate, 20);
+    }
+
+    #[test]
+    fn keeper_estimate_stops_at_next_rate_change() {
+        let mut open = stream(10, 10_000, 0, None);
+        // 10_000 buffer, 100 s minimum at rate 10: below the minimum after 900 s.
+        assert_eq!(StreamCoreState::seconds_until_liquidatable(&open, 0, 100), Some(901));
+
+        open.rate_changes.push(RateChange { at: 300, new_rate: 20 });
+        assert_eq!(StreamCoreState::seconds_until_liquidatable(&open, 0, 100), Some(300));
+
+        open.rate_changes.clear();
+        open.curve = ReleaseCurve::PiecewiseLinear {
+            segments: vec![CurveSegment { duration: 200, rate_bps: 10_000 }],
+        };
+        assert_eq!(StreamCoreState::seconds_until_liquidatable(&open, 0, 100), Some(200));
+        assert_eq!(StreamCoreState::seconds_until_liquidatable(&open, 200, 100), Some(701));
+    }
+
+    #[test]
+    fn fixed_stream_accrues_nothing_before_start() {
+        let fixed = stream(0, 500, 1_000, Some(2_000));
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 900), 0);
+        assert_eq!(StreamCoreState::total_streamed(&fixed, 1_500), 250);
     }
 }
@@ -15,6 +15,38 @@ fn encode_call(service: &str, method: &str, args: impl Encode) -> Vec<u8> {
     payload
 }
 
+/// Gas attached to every VFT transfer the vault sends. It comes out of the
+/// calling message's gas, so callers must attach this much per transfer on
+/// top of what the vault itself uses.
+pub const VFT_TRANSFER_GAS: u64 = 5_000_000_000;
+
+fn send_to_receiver(token: ActorId, receiver: ActorId, amount: u128) {
+    if token == ActorId::zero() {
+        msg::send(receiver, b"", amount).expect("Failed to send native VARA");
+    } else {
+        // Send tokens to receiver via VFT transfer(receiver, amount)
+        let payload = encode_call(
+            "VftService",
+            "Transfer",
+            (receiver, amount),
+        );
+        gstd_msg::send_bytes_with_gas(token, payload, VFT_TRANSFER_GAS, 0)
+            .expect("VFT transfer to receiver failed");
+    }
+}
+
+/// Sums batch entries per token, preserving first-seen token order.
+fn totals_by_token(entries: &[StreamAllocation]) -> Vec<(ActorId, u128)> {
+    let mut totals: Vec<(ActorId, u128)> = Vec::new();
+    for entry in entries {
+        match totals.iter_mut().find(|(token, _)| *token == entry.token) {
+            Some((_, total)) => *total = total.saturating_add(entry.amount),
+            None => totals.push((entry.token, entry.amount)),
+        }
+    }
+    totals
+}
+
 // ---------------------------------------------------------------------------
 // Types
 // ---------------------------------------------------------------------------
@@ -28,6 +60,14 @@ pub struct VaultBalance {
     pub available: u128,
 }
 
+/// One stream's share of a batched allocate/release/transfer call.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+pub struct StreamAllocation {
+    pub stream_id: u64,
+    pub token: ActorId,
+    pub amount: u128,
+}
+
 #[derive(Debug, Clone, Encode, Decode, TypeInfo)]
 pub struct VaultConfig {
     pub admin: ActorId,
@@ -45,6 +85,8 @@ pub struct TokenVaultState {
     pub config: VaultConfig,
     pub balances: BTreeMap<(ActorId, ActorId), VaultBalance>,
     pub stream_allocations: BTreeMap<u64, u128>,
+    /// Protocol fees collected from stream withdrawals, per token.
+    pub fees_accrued: BTreeMap<ActorId, u128>,
 }
 
 impl TokenVaultState {
@@ -57,6 +99,7 @@ impl TokenVaultState {
             },
             balances: BTreeMap::new(),
             stream_allocations: BTreeMap::new(),
+            fees_accrued: BTreeMap::new(),
         }
     }
 
@@ -73,6 +116,23 @@ impl TokenVaultState {
             available: 0,
         })
     }
+
+    /// Moves a withdrawal's protocol fee out of the stream's allocation into
+    /// the fee treasury.
+    fn collect_fee(&mut self, stream_id: u64, token: ActorId, fee: u128) {
+        if fee == 0 {
+            return;
+        }
+        let alloc = self
+            .stream_allocations
+            .get_mut(&stream_id)
+            .expect("No allocation found");
+        assert!(*alloc >= fee, "Fee exceeds allocation");
+        *alloc = alloc.saturating_sub(fee);
+
+        let accrued = self.fees_accrued.entry(token).or_insert(0);
+        *accrued = accrued.saturating_add(fee);
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -127,7 +187,7 @@ impl VaultService {
             "TransferFrom",
             (caller, vault_id, amount),
         );
-        gstd_msg::send_bytes_with_gas(token, payload, 5_000_000_000, 0)
+        gstd_msg::send_bytes_with_gas(token, payload, VFT_TRANSFER_GAS, 0)
             .expect("VFT transfer_from failed");
 
         let balance = state.get_or_create_balance(caller, token);
@@ -166,7 +226,7 @@ impl VaultService {
             "Transfer",
             (caller, amount),
         );
-        gstd_msg::send_bytes_with_gas(token, payload, 5_000_000_000, 0)
+        gstd_msg::send_bytes_with_gas(token, payload, VFT_TRANSFER_GAS, 0)
             .expect("VFT transfer failed");
     }
 
@@ -236,12 +296,15 @@ impl VaultService {
         balance.available = balance.available.saturating_add(amount);
     }
 
+    /// Pays `amount` to `receiver` and moves `fee` to the fee treasury, both
+    /// out of the stream's allocation.
     pub fn transfer_to_receiver(
         &mut self,
         token: ActorId,
         receiver: ActorId,
         amount: u128,
         stream_id: u64,
+        fee: u128,
     ) {
         let state = TokenVaultState::get();
         let caller = msg::source();
@@ -256,21 +319,170 @@ impl VaultService {
             .expect("No allocation found");
         assert!(*alloc >= amount, "Transfer amount exceeds allocation");
         *alloc = alloc.saturating_sub(amount);
+        state.collect_fee(stream_id, token, fee);
+
+        send_to_receiver(token, receiver, amount);
+    }
+
+    /// Pays a stream's funds into `owner`'s available vault balance instead of
+    /// sending them out, so they can fund new streams right away.
+    pub fn credit_from_stream(
+        &mut self,
+        owner: ActorId,
+        token: ActorId,
+        amount: u128,
+        stream_id: u64,
+        fee: u128,
+    ) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(
+            caller == state.config.stream_core,
+            "Only StreamCore can credit from a stream"
+        );
+
+        let alloc = state
+            .stream_allocations
+            .get_mut(&stream_id)
+            .expect("No allocation found");
+        assert!(*alloc >= amount, "Credit amount exceeds allocation");
+        *alloc = alloc.saturating_sub(amount);
+        state.collect_fee(stream_id, token, fee);
 
-        if token == ActorId::zero() {
-            msg::send(receiver, b"", amount).expect("Failed to send native VARA");
-        } else {
-            // Send tokens to receiver via VFT transfer(receiver, amount)
-            let payload = encode_call(
-                "VftService",
-                "Transfer",
-                (receiver, amount),
+        let balance = state.get_or_create_balance(owner, token);
+        balance.total_deposited = balance.total_deposited.saturating_add(amount);
+        balance.available = balance.available.saturating_add(amount);
+    }
+
+    /// Batched `allocate_to_stream`: one balance check and update per token.
+    pub fn allocate_to_streams(&mut self, owner: ActorId, allocations: Vec<StreamAllocation>) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(
+            caller == state.config.stream_core,
+            "Only StreamCore can allocate"
+        );
+
+        for (token, total) in totals_by_token(&allocations) {
+            let balance = state.get_or_create_balance(owner, token);
+            assert!(
+                balance.available >= total,
+                "Insufficient available balance for allocation"
             );
-            gstd_msg::send_bytes_with_gas(token, payload, 5_000_000_000, 0)
-                .expect("VFT transfer to receiver failed");
+            balance.available = balance.available.saturating_sub(total);
+            balance.total_allocated = balance.total_allocated.saturating_add(total);
+        }
+
+        for entry in allocations {
+            let current = state.stream_allocations.entry(entry.stream_id).or_insert(0);
+            *current = current.saturating_add(entry.amount);
+        }
+    }
+
+    /// Batched `release_from_stream`: one balance update per token.
+    pub fn release_from_streams(&mut self, owner: ActorId, releases: Vec<StreamAllocation>) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(
+            caller == state.config.stream_core,
+            "Only StreamCore can release"
+        );
+
+        for entry in &releases {
+            let alloc = state
+                .stream_allocations
+                .get_mut(&entry.stream_id)
+                .expect("No allocation found");
+            assert!(*alloc >= entry.amount, "Release amount exceeds allocation");
+            *alloc = alloc.saturating_sub(entry.amount);
+        }
+
+        for (token, total) in totals_by_token(&releases) {
+            let balance = state.get_or_create_balance(owner, token);
+            balance.total_allocated = balance.total_allocated.saturating_sub(total);
+            balance.available = balance.available.saturating_add(total);
+        }
+    }
+
+    /// Like `transfer_to_receiver`, but splits the payout across several
+    /// recipients, e.g. the members of a split group.
+    pub fn transfer_to_recipients(
+        &mut self,
+        token: ActorId,
+        recipients: Vec<(ActorId, u128)>,
+        stream_id: u64,
+        fee: u128,
+    ) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(
+            caller == state.config.stream_core,
+            "Only StreamCore can transfer to receiver"
+        );
+
+        let amount = recipients
+            .iter()
+            .fold(0u128, |total, (_, amount)| total.saturating_add(*amount));
+        let alloc = state
+            .stream_allocations
+            .get_mut(&stream_id)
+            .expect("No allocation found");
+        assert!(*alloc >= amount, "Transfer amount exceeds allocation");
+        *alloc = alloc.saturating_sub(amount);
+        state.collect_fee(stream_id, token, fee);
+
+        for (recipient, amount) in recipients {
+            if amount > 0 {
+                send_to_receiver(token, recipient, amount);
+            }
         }
     }
 
+    /// Batched `transfer_to_receiver`: one payout to `receiver` per token,
+    /// with each stream's fee listed in `fees`.
+    pub fn transfer_to_receiver_batch(
+        &mut self,
+        receiver: ActorId,
+        transfers: Vec<StreamAllocation>,
+        fees: Vec<StreamAllocation>,
+    ) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(
+            caller == state.config.stream_core,
+            "Only StreamCore can transfer to receiver"
+        );
+
+        for entry in &transfers {
+            let alloc = state
+                .stream_allocations
+                .get_mut(&entry.stream_id)
+                .expect("No allocation found");
+            assert!(*alloc >= entry.amount, "Transfer amount exceeds allocation");
+            *alloc = alloc.saturating_sub(entry.amount);
+        }
+        for entry in fees {
+            state.collect_fee(entry.stream_id, entry.token, entry.amount);
+        }
+
+        for (token, total) in totals_by_token(&transfers) {
+            send_to_receiver(token, receiver, total);
+        }
+    }
+
+    /// Pays collected protocol fees out of the fee treasury to `to`.
+    pub fn withdraw_fees(&mut self, token: ActorId, amount: u128, to: ActorId) {
+        let state = TokenVaultState::get();
+        let caller = msg::source();
+        assert!(caller == state.config.admin, "Only admin can withdraw fees");
+
+        let accrued = state.fees_accrued.entry(token).or_insert(0);
+        assert!(*accrued >= amount, "Amount exceeds accrued fees");
+        *accrued = accrued.saturating_sub(amount);
+
+        send_to_receiver(token, to, amount);
+    }
+
     pub fn emergency_pause(&mut self) {
         let state = TokenVaultState::get();
         let caller = msg::source();
@@ -318,6 +530,19 @@ impl VaultService {
             .unwrap_or(0)
     }
 
+    /// `get_stream_allocation` for several streams, in input order.
+    pub fn get_stream_allocations(&self, stream_ids: Vec<u64>) -> Vec<u128> {
+        stream_ids
+            .into_iter()
+            .map(|stream_id| self.get_stream_allocation(stream_id))
+            .collect()
+    }
+
+    pub fn get_fees_accrued(&self, token: ActorId) -> u128 {
+        let state = TokenVaultState::get();
+        state.fees_accrued.get(&token).copied().unwrap_or(0)
+    }
+
     pub fn is_paused(&self) -> bool {
         let state = TokenVaultState::get();
         state.config.paused